@@ -0,0 +1,275 @@
+/* BroadPhase: a dynamic AABB tree that finds which things might be touching, so that the exact
+ * tests in collision.rs only run on pairs that are close. Each leaf holds a key and a box a
+ * little larger than the thing it stands for; things that move without leaving their box don't
+ * change the tree at all. New leaves go next to the sibling that grows the tree's total surface
+ * area the least, which keeps nearby things in the same branches.
+ */
+use std::{collections::HashMap, hash::Hash};
+
+use crate::collision::Aabb;
+
+// How much bigger than the thing itself a leaf's box is.
+const MARGIN: f32 = 0.1;
+
+#[derive(Clone)]
+enum Kind<K> {
+    Leaf(K),
+    Branch([usize; 2]),
+    // On the free list.
+    Free,
+}
+
+#[derive(Clone)]
+struct Node<K> {
+    aabb: Aabb,
+    parent: Option<usize>,
+    kind: Kind<K>,
+}
+
+#[derive(Clone)]
+pub struct BroadPhase<K> {
+    nodes: Vec<Node<K>>,
+    root: Option<usize>,
+    free: Vec<usize>,
+    leaves: HashMap<K, usize>,
+}
+impl<K: Copy + Eq + Hash> BroadPhase<K> {
+    pub fn new() -> Self {
+        BroadPhase { nodes: vec![], root: None, free: vec![], leaves: HashMap::new() }
+    }
+    pub fn contains(&self, key: K) -> bool {
+        self.leaves.contains_key(&key)
+    }
+    // Adds `key`, or moves it if it's already there.
+    pub fn insert(&mut self, key: K, aabb: Aabb) {
+        if self.contains(key) {
+            self.update(key, aabb);
+            return;
+        }
+        let leaf = self.allocate(Node {
+            aabb: aabb.expanded(MARGIN),
+            parent: None,
+            kind: Kind::Leaf(key),
+        });
+        self.leaves.insert(key, leaf);
+        self.insert_leaf(leaf);
+    }
+    // Moves `key` to `aabb`. Only changes the tree if it has left its old leaf's box. Does nothing
+    // for keys that aren't there.
+    pub fn update(&mut self, key: K, aabb: Aabb) {
+        let Some(&leaf) = self.leaves.get(&key) else {
+            return;
+        };
+        if self.nodes[leaf].aabb.contains(&aabb) {
+            return;
+        }
+        self.remove_leaf(leaf);
+        self.nodes[leaf].aabb = aabb.expanded(MARGIN);
+        self.insert_leaf(leaf);
+    }
+    // Every key whose box overlaps `aabb`.
+    pub fn query(&self, aabb: &Aabb) -> impl Iterator<Item = K> + '_ {
+        let mut stack: Vec<usize> = self.root.into_iter().collect();
+        let aabb = *aabb;
+        std::iter::from_fn(move || {
+            while let Some(index) = stack.pop() {
+                let node = &self.nodes[index];
+                if !node.aabb.overlaps(&aabb) {
+                    continue;
+                }
+                match node.kind {
+                    Kind::Leaf(key) => return Some(key),
+                    Kind::Branch(children) => stack.extend(children),
+                    Kind::Free => unreachable!("free node {index} is in the tree"),
+                }
+            }
+            None
+        })
+    }
+    // Every pair of keys whose boxes overlap, each pair once. The order only depends on the inserts
+    // and moves that built the tree, so the same changes always give the same pairs in the same
+    // order.
+    pub fn pairs(&self) -> Vec<(K, K)> {
+        let mut pairs = vec![];
+        for (leaf, node) in self.nodes.iter().enumerate() {
+            let Kind::Leaf(key) = node.kind else {
+                continue;
+            };
+            for other in self.query(&node.aabb) {
+                // Each pair is found from both ends; keep the one found from the earlier leaf.
+                if self.leaves[&other] > leaf {
+                    pairs.push((key, other));
+                }
+            }
+        }
+        pairs
+    }
+
+    fn allocate(&mut self, node: Node<K>) -> usize {
+        match self.free.pop() {
+            Some(index) => {
+                self.nodes[index] = node;
+                index
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        }
+    }
+    fn release(&mut self, index: usize) {
+        self.nodes[index].kind = Kind::Free;
+        self.nodes[index].parent = None;
+        self.free.push(index);
+    }
+    fn insert_leaf(&mut self, leaf: usize) {
+        let Some(root) = self.root else {
+            self.root = Some(leaf);
+            self.nodes[leaf].parent = None;
+            return;
+        };
+        let aabb = self.nodes[leaf].aabb;
+        // Walk down to the sibling that's cheapest to pair with. Pairing with a branch costs
+        // the area of the new branch; going further down also grows the branch by what it takes
+        // to fit the leaf, which every level above pays too.
+        let mut sibling = root;
+        while let Kind::Branch(children) = self.nodes[sibling].kind {
+            let area = self.nodes[sibling].aabb.surface_area();
+            let combined = self.nodes[sibling].aabb.union(&aabb).surface_area();
+            let here = 2.0 * combined;
+            let inherited = 2.0 * (combined - area);
+            let descend = |child: usize| {
+                let node = &self.nodes[child];
+                let grown = node.aabb.union(&aabb).surface_area();
+                match node.kind {
+                    Kind::Leaf(_) => grown + inherited,
+                    _ => grown - node.aabb.surface_area() + inherited,
+                }
+            };
+            let costs = children.map(descend);
+            if here < costs[0] && here < costs[1] {
+                break;
+            }
+            sibling = if costs[0] <= costs[1] { children[0] } else { children[1] };
+        }
+
+        let old_parent = self.nodes[sibling].parent;
+        let branch = self.allocate(Node {
+            aabb: self.nodes[sibling].aabb.union(&aabb),
+            parent: old_parent,
+            kind: Kind::Branch([sibling, leaf]),
+        });
+        self.nodes[sibling].parent = Some(branch);
+        self.nodes[leaf].parent = Some(branch);
+        match old_parent {
+            Some(parent) => self.replace_child(parent, sibling, branch),
+            None => self.root = Some(branch),
+        }
+        self.refit(old_parent);
+    }
+    fn remove_leaf(&mut self, leaf: usize) {
+        let Some(parent) = self.nodes[leaf].parent else {
+            self.root = None;
+            return;
+        };
+        let Kind::Branch(children) = self.nodes[parent].kind else {
+            unreachable!("the parent of {leaf} isn't a branch");
+        };
+        let sibling = if children[0] == leaf { children[1] } else { children[0] };
+        // The sibling takes the parent's place.
+        let grandparent = self.nodes[parent].parent;
+        self.nodes[sibling].parent = grandparent;
+        match grandparent {
+            Some(grandparent) => self.replace_child(grandparent, parent, sibling),
+            None => self.root = Some(sibling),
+        }
+        self.release(parent);
+        self.nodes[leaf].parent = None;
+        self.refit(grandparent);
+    }
+    fn replace_child(&mut self, parent: usize, old: usize, new: usize) {
+        if let Kind::Branch(children) = &mut self.nodes[parent].kind {
+            for child in children.iter_mut().filter(|child| **child == old) {
+                *child = new;
+            }
+        }
+    }
+    // Shrinks or grows the boxes of `index` and everything above it to fit their children.
+    fn refit(&mut self, mut index: Option<usize>) {
+        while let Some(i) = index {
+            if let Kind::Branch([a, b]) = self.nodes[i].kind {
+                self.nodes[i].aabb = self.nodes[a].aabb.union(&self.nodes[b].aabb);
+            }
+            index = self.nodes[i].parent;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use cgmath::Vector3;
+
+    fn cube(x: f32, y: f32, z: f32) -> Aabb {
+        let center = Vector3::new(x, y, z);
+        Aabb {
+            min: center - Vector3::new(0.5, 0.5, 0.5),
+            max: center + Vector3::new(0.5, 0.5, 0.5),
+        }
+    }
+
+    fn sorted(keys: impl Iterator<Item = usize>) -> Vec<usize> {
+        let mut keys: Vec<usize> = keys.collect();
+        keys.sort();
+        keys
+    }
+
+    #[test]
+    fn queries_follow_inserts_and_moves() {
+        let mut tree = BroadPhase::new();
+        // A row of cubes with gaps between them.
+        for i in 0..20 {
+            tree.insert(i, cube(i as f32 * 2.0, 0.0, 0.0));
+        }
+        assert_eq!(sorted(tree.query(&cube(4.0, 0.0, 0.0))), [2]);
+        assert_eq!(sorted(tree.query(&cube(5.0, 0.0, 0.0))), [2, 3]);
+
+        // Moving 2 onto 3 makes them a pair, and takes 2 away from where it was.
+        tree.update(2, cube(6.5, 0.0, 0.0));
+        assert_eq!(sorted(tree.query(&cube(4.0, 0.0, 0.0))), []);
+        let pairs: Vec<(usize, usize)> = tree.pairs();
+        assert_eq!(pairs.len(), 1);
+        assert!(pairs[0] == (2, 3) || pairs[0] == (3, 2));
+
+        // Moving back apart leaves the tree as it was.
+        tree.update(2, cube(4.0, 0.0, 0.0));
+        assert!(tree.pairs().is_empty());
+        assert_eq!(sorted(tree.query(&cube(5.0, 0.0, 0.0))), [2, 3]);
+    }
+
+    #[test]
+    fn pairs_are_found_once_in_a_repeatable_order() {
+        // A 4x4 grid of touching cubes, with one moved so that it overlaps four others.
+        let build = || {
+            let mut tree = BroadPhase::new();
+            for i in 0..16 {
+                tree.insert(i, cube((i % 4) as f32, (i / 4) as f32, 0.0));
+            }
+            tree.update(0, cube(1.5, 1.5, 0.0));
+            tree
+        };
+        let pairs = build().pairs();
+        let mut unordered: Vec<(usize, usize)> =
+            pairs.iter().map(|&(a, b)| (a.min(b), a.max(b))).collect();
+        unordered.sort();
+        unordered.dedup();
+        assert_eq!(unordered.len(), pairs.len());
+        // Neighbours along rows, columns and diagonals, less the three 0 had in its corner, plus
+        // its new neighbours 5, 6, 9 and 10.
+        assert_eq!(pairs.len(), 12 + 12 + 18 - 3 + 4);
+        assert!(unordered.contains(&(0, 10)));
+        assert!(!unordered.contains(&(0, 4)));
+        assert_eq!(build().pairs(), pairs);
+    }
+}
@@ -1,8 +1,9 @@
-use cgmath::{Deg, Matrix4, Point3, Vector3};
+use cgmath::{Deg, InnerSpace, Matrix4, Point3, Vector3, VectorSpace};
 
 #[derive(Clone, Copy)]
 pub struct Camera {
     pub eye: Point3<f32>, // position of the camera
+    #[allow(unused)]
     pub velocity: Vector3<f32>,
     pub direction: Vector3<f32>,
     up: Vector3<f32>,
@@ -24,11 +25,21 @@ impl Camera {
     ) -> Self {
         Camera { eye, velocity: (0.0, 0.0, 0.0).into(), direction, up, aspect, fovy, znear, zfar }
     }
+    // Blends the eye and view direction from self (t = 0) to other (t = 1). Everything else is
+    // taken from other.
+    pub fn lerp(&self, other: &Camera, t: f32) -> Camera {
+        let direction = self.direction.lerp(other.direction, t);
+        Camera {
+            eye: self.eye + (other.eye - self.eye) * t,
+            direction: if direction.magnitude2() > 0.0 { direction } else { other.direction },
+            ..*other
+        }
+    }
     pub fn build_view_projection_matrix(&self) -> Matrix4<f32> {
         let view = cgmath::Matrix4::look_to_rh(self.eye, self.direction, self.up);
         let proj = cgmath::perspective(Deg(self.fovy), self.aspect, self.znear, self.zfar);
 
-        return OPENGL_TO_WGPU_MATRIX * proj * view;
+        OPENGL_TO_WGPU_MATRIX * proj * view
     }
     pub fn set_aspect(&mut self, aspect: f32) {
         self.aspect = aspect;
@@ -68,7 +79,7 @@ impl CameraUniform {
     }
     pub fn from_camera(camera: &Camera) -> Self {
         let mut uniform = CameraUniform::new();
-        uniform.update_view_proj(&camera);
-        return uniform;
+        uniform.update_view_proj(camera);
+        uniform
     }
 }
\ No newline at end of file
@@ -0,0 +1,136 @@
+/* Capture: writes rendered frames to PNG files, either as single screenshots or as a numbered
+ * sequence, which is handy for making videos. Recorded frames are a fixed number of game ticks
+ * apart, and the game only runs those ticks once the previous frame is saved, so a recording
+ * plays back at its frame rate however long each frame took to render and save. */
+use anyhow::{anyhow, Context, Result};
+use image::RgbaImage;
+use std::{
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+const DEFAULT_CAPTURE_DIR: &str = "captures";
+// Two ticks per frame at the default tick rate.
+const DEFAULT_RECORD_FPS: f32 = 50.0;
+// How often the GPU thread checks for the game's next frame while recording.
+pub const RECORDING_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+pub struct CaptureOptions {
+    pub dir: PathBuf,
+    // Number of consecutive frames to record from startup. Zero disables recording.
+    pub record_frames: u32,
+    // Rounded to a whole number of game ticks per frame.
+    pub record_fps: f32,
+    // Render offscreen at this size instead of opening a window.
+    pub headless: Option<(u32, u32)>,
+    // Write the input of every game tick to this file.
+    pub record_input: Option<PathBuf>,
+    // Play back an input recording and check it against the simulation, instead of running the
+    // game.
+    pub replay: Option<PathBuf>,
+}
+impl Default for CaptureOptions {
+    fn default() -> Self {
+        CaptureOptions {
+            dir: PathBuf::from(DEFAULT_CAPTURE_DIR),
+            record_frames: 0,
+            record_fps: DEFAULT_RECORD_FPS,
+            headless: None,
+            record_input: None,
+            replay: None,
+        }
+    }
+}
+
+pub struct Capture {
+    dir: PathBuf,
+    screenshot_requested: bool,
+    frames_remaining: u32,
+    next_frame: u32,
+    ticks_per_frame: u32,
+    timestep: f32,
+}
+impl Capture {
+    pub fn new(options: &CaptureOptions, time_per_tick: Duration) -> Self {
+        let tick_secs = time_per_tick.as_secs_f32();
+        let ticks_per_frame = (1.0 / (options.record_fps * tick_secs)).round().max(1.0) as u32;
+        Capture {
+            dir: options.dir.clone(),
+            screenshot_requested: false,
+            frames_remaining: options.record_frames,
+            next_frame: 0,
+            ticks_per_frame,
+            timestep: ticks_per_frame as f32 * tick_secs,
+        }
+    }
+    pub fn request_screenshot(&mut self) {
+        self.screenshot_requested = true;
+    }
+    // Drops any pending screenshot and stops recording.
+    pub fn cancel(&mut self) {
+        self.screenshot_requested = false;
+        self.frames_remaining = 0;
+    }
+    pub fn is_recording(&self) -> bool {
+        self.frames_remaining > 0
+    }
+    // How many game ticks to run between recorded frames.
+    pub fn ticks_per_frame(&self) -> u32 {
+        self.ticks_per_frame
+    }
+    // Whether the next rendered frame should be read back and saved.
+    pub fn wants_frame(&self) -> bool {
+        self.screenshot_requested || self.is_recording()
+    }
+    // While recording, the shader time of the next frame: the game time it shows, since recording
+    // starts at the first tick.
+    pub fn frame_time(&self) -> Option<f32> {
+        self.is_recording().then_some(self.next_frame as f32 * self.timestep)
+    }
+    // Saves a frame of tightly packed RGBA8 rows and returns the path it was written to.
+    pub fn save_frame(&mut self, width: u32, height: u32, rgba: Vec<u8>) -> Result<PathBuf> {
+        std::fs::create_dir_all(&self.dir)
+            .with_context(|| format!("Could not create {}", self.dir.display()))?;
+        let file_name = if self.is_recording() {
+            self.frames_remaining -= 1;
+            self.next_frame += 1;
+            format!("frame-{:05}.png", self.next_frame - 1)
+        } else {
+            let millis =
+                SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis();
+            format!("screenshot-{millis}.png")
+        };
+        self.screenshot_requested = false;
+        let path = self.dir.join(file_name);
+        let image = RgbaImage::from_raw(width, height, rgba)
+            .ok_or_else(|| anyhow!("Frame does not match its {width}x{height} size"))?;
+        image.save(&path).with_context(|| format!("Could not write {}", path.display()))?;
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TICK: Duration = Duration::from_millis(10);
+
+    fn capture(record_fps: f32) -> Capture {
+        Capture::new(&CaptureOptions { record_frames: 3, record_fps, ..Default::default() }, TICK)
+    }
+
+    #[test]
+    fn frames_are_whole_ticks_apart() {
+        assert_eq!(capture(50.0).ticks_per_frame(), 2);
+        // 60 frames per second is 1.67 ticks, so it's recorded at 50.
+        assert_eq!(capture(60.0).ticks_per_frame(), 2);
+        assert_eq!(capture(30.0).ticks_per_frame(), 3);
+        // Frames can't be closer together than the game updates.
+        assert_eq!(capture(1000.0).ticks_per_frame(), 1);
+
+        // The shader time of each frame is the game time of the ticks before it.
+        let mut capture = capture(30.0);
+        capture.next_frame = 2;
+        assert!((capture.frame_time().unwrap() - 0.06).abs() < 1e-6);
+    }
+}
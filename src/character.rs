@@ -0,0 +1,320 @@
+/* Character: the player's body. It isn't a rigid body; nothing pushes it around but its own
+ * velocity. Each tick it works out how far it would go, sweeps its box along that path, stops at
+ * the first thing in the way and slides along it with whatever is left of the move. Ground
+ * tilted less than the max slope is walkable: standing on it holds the character up against
+ * gravity and lets it jump, walking up it follows the slope, and walking off a step or down a
+ * slope keeps it on the ground rather than launching it. Anything in the way no taller than the
+ * step height it climbs onto. The camera rides along at eye height.
+ *
+ * Sweeps only need to know whether the box overlaps anything at a given place, so they step
+ * along the path and then bisect the first step that ends up overlapping.
+ */
+use cgmath::{Angle, Deg, InnerSpace, Point3, Vector3};
+
+use crate::{
+    camera::Camera,
+    collision::{Aabb, Contact, Hull},
+    physics::{Collision, Physics},
+};
+
+// The box is a quarter wide and as tall as the eye is above the floor.
+const HALF_WIDTH: f32 = 0.125;
+const HALF_HEIGHT: f32 = 0.5;
+// How far above the middle of the box the eye is.
+const EYE_HEIGHT: f32 = 0.4;
+// The character never goes faster than this.
+const MAX_SPEED: f32 = 10.0;
+// Sweeps go in steps no longer than this, so that they can't pass through anything thinner than
+// the character.
+const SWEEP_STEP: f32 = HALF_WIDTH;
+// How close a sweep gets to where it first touches something.
+const SWEEP_TOLERANCE: f32 = 1e-5;
+// How many surfaces one move can slide along before giving up on the rest of it.
+const MAX_SLIDES: usize = 4;
+// Things that move into the character push it out; getting out of one can push it into another.
+const MAX_DEPENETRATION_PASSES: usize = 4;
+// How far the character keeps from anything it runs into, so that it doesn't start the next move
+// already touching it.
+const SKIN: f32 = 1e-4;
+// How far under its feet the character looks for ground while it's in the air. Any further and
+// it would land before it got there.
+const GROUND_PROBE: f32 = 0.001;
+// A step only counts if it gets further than this past where walking ran into it.
+const MIN_STEP_PROGRESS: f32 = 0.001;
+
+// Whatever the character can run into.
+pub trait World {
+    // Names the thing the character ran into.
+    type Key: Copy;
+    // The deepest overlap between `shape`, which fits in `bounds`, and anything in the world, with
+    // a contact that moves the shape out of it.
+    fn overlap(&self, shape: &Hull, bounds: &Aabb) -> Option<(Self::Key, Contact)>;
+    // Whether overlap() would find anything, which sweeps ask far more often.
+    fn overlaps(&self, shape: &Hull, bounds: &Aabb) -> bool;
+}
+
+// Something the character ran into on its way.
+#[derive(Clone, Copy, Debug)]
+pub struct Hit<K> {
+    pub key: K,
+    // Out of what was hit, toward the character.
+    pub normal: Vector3<f32>,
+    // Where the character's box was when it hit.
+    pub bounds: Aabb,
+    // How fast it was going, before hitting took any of that away.
+    pub velocity: Vector3<f32>,
+}
+
+// Where a sweep first ran into something.
+struct Sweep<K> {
+    key: K,
+    // How much of the way it got before it touched.
+    fraction: f32,
+    normal: Vector3<f32>,
+}
+
+#[derive(Clone)]
+pub struct Character {
+    pub physics: Physics,
+    pub camera: Camera,
+    // Ground is walkable if its normal is at least this close to straight up: the cosine of the
+    // max slope.
+    min_ground_normal_y: f32,
+    step_height: f32,
+    // The normal of the walkable ground it's standing on, or None in the air.
+    ground: Option<Vector3<f32>>,
+}
+impl Character {
+    // Puts the eye of `camera` where the character's is.
+    pub fn new(position: Point3<f32>, camera: Camera, max_slope: f32, step_height: f32) -> Self {
+        let mut physics = Physics::new();
+        let (w, h) = (HALF_WIDTH, HALF_HEIGHT);
+        let corners = (0..8).map(|i| {
+            let sign = |bit: usize| if i & bit == 0 { 1.0 } else { -1.0 };
+            Vector3::new(sign(1) * w, sign(2) * h, sign(4) * w)
+        });
+        physics.collision = Collision::new(corners.collect(), vec![]);
+        physics.position = position;
+        let mut character = Character {
+            physics,
+            camera,
+            min_ground_normal_y: Deg(max_slope).cos(),
+            step_height,
+            ground: None,
+        };
+        character.place_eye();
+        character
+    }
+    pub fn is_grounded(&self) -> bool {
+        self.ground.is_some()
+    }
+    // The normal of the ground under the character, if it's standing on walkable ground.
+    #[cfg(test)]
+    pub fn ground_normal(&self) -> Option<Vector3<f32>> {
+        self.ground
+    }
+    // Looks for ground under a character that has just been put somewhere.
+    pub fn land<W: World>(&mut self, world: &W) {
+        self.ground = self.find_ground(world, false);
+    }
+    // Leaves the ground at `speed`. Does nothing in the air.
+    pub fn jump(&mut self, speed: f32) {
+        if self.ground.take().is_some() {
+            self.physics.velocity.y += speed;
+        }
+    }
+    // Moves on by one tick at the velocity and acceleration in physics. Returns everything it ran
+    // into, including things that moved into it since the last tick.
+    pub fn step<W: World>(&mut self, delta_t: f32, world: &W) -> Vec<Hit<W::Key>> {
+        let mut hits = vec![];
+        self.depenetrate(world, &mut hits);
+        // On the ground, it goes along the ground: up slopes as well as down them.
+        if let Some(normal) = self.ground {
+            let velocity = self.physics.velocity;
+            self.physics.velocity -= velocity.dot(normal) * normal;
+        }
+        let was_grounded = self.is_grounded();
+        let delta = self.physics.displacement(delta_t, MAX_SPEED);
+        self.slide(world, delta, was_grounded, &mut hits);
+        // Walking up a slope goes up, but shouldn't lose the ground; jumping should.
+        self.ground = if was_grounded || self.physics.velocity.y <= 0.0 {
+            self.find_ground(world, was_grounded)
+        } else {
+            None
+        };
+        self.place_eye();
+        hits
+    }
+    fn place_eye(&mut self) {
+        self.camera.eye = self.physics.position + Vector3::new(0.0, EYE_HEIGHT, 0.0);
+    }
+    fn is_walkable(&self, normal: Vector3<f32>) -> bool {
+        normal.y >= self.min_ground_normal_y
+    }
+    // The box at `offset` from where the character is.
+    fn hull(&self, offset: Vector3<f32>) -> Hull<'_> {
+        let hull = self.physics.hull();
+        Hull { offset: hull.offset + offset, ..hull }
+    }
+    fn overlap<W: World>(&self, world: &W, offset: Vector3<f32>) -> Option<(W::Key, Contact)> {
+        let hull = self.hull(offset);
+        world.overlap(&hull, &hull.aabb())
+    }
+    fn overlaps<W: World>(&self, world: &W, offset: Vector3<f32>) -> bool {
+        let hull = self.hull(offset);
+        world.overlaps(&hull, &hull.aabb())
+    }
+    // Where moving by `delta` first runs into something, or None if the way is clear.
+    fn sweep<W: World>(&self, world: &W, delta: Vector3<f32>) -> Option<Sweep<W::Key>> {
+        let length = delta.magnitude();
+        if length == 0.0 {
+            return None;
+        }
+        let steps = (length / SWEEP_STEP).ceil() as usize;
+        let mut clear = 0.0;
+        for step in 1..=steps {
+            let fraction = step as f32 / steps as f32;
+            if !self.overlaps(world, delta * fraction) {
+                clear = fraction;
+                continue;
+            }
+            let mut blocked = fraction;
+            while (blocked - clear) * length > SWEEP_TOLERANCE {
+                let middle = (clear + blocked) / 2.0;
+                if self.overlaps(world, delta * middle) {
+                    blocked = middle;
+                } else {
+                    clear = middle;
+                }
+            }
+            let (key, contact) = self.overlap(world, delta * blocked)?;
+            return Some(Sweep { key, fraction: clear, normal: contact.normal });
+        }
+        None
+    }
+    // Moves by `delta`, or as far as `sweep` got along it, staying SKIN away from what it hit.
+    fn advance<K>(&mut self, delta: Vector3<f32>, sweep: Option<&Sweep<K>>) {
+        self.physics.position += match sweep {
+            Some(sweep) => delta * sweep.fraction + sweep.normal * SKIN,
+            None => delta,
+        };
+    }
+    // Moves down onto the ground `sweep` found. It backs off straight up rather than along the
+    // normal, which would creep it down slopes.
+    fn settle<K>(&mut self, down: Vector3<f32>, sweep: &Sweep<K>) {
+        self.physics.position +=
+            down * sweep.fraction + Vector3::unit_y() * (SKIN / sweep.normal.y);
+    }
+    // Gets out of anything that has moved into the character.
+    fn depenetrate<W: World>(&mut self, world: &W, hits: &mut Vec<Hit<W::Key>>) {
+        for _ in 0..MAX_DEPENETRATION_PASSES {
+            let hull = self.physics.hull();
+            let bounds = hull.aabb();
+            let Some((key, contact)) = world.overlap(&hull, &bounds) else {
+                return;
+            };
+            let velocity = self.physics.velocity;
+            hits.push(Hit { key, normal: contact.normal, bounds, velocity });
+            self.physics.position += contact.normal * (contact.depth + SKIN);
+            remove_into(&mut self.physics.velocity, contact.normal);
+        }
+    }
+    // Moves by `delta`, sliding along whatever is in the way. On the ground, walls can be stepped
+    // over and steep slopes act as walls.
+    fn slide<W: World>(
+        &mut self,
+        world: &W,
+        delta: Vector3<f32>,
+        grounded: bool,
+        hits: &mut Vec<Hit<W::Key>>,
+    ) {
+        let mut remaining = delta;
+        for _ in 0..MAX_SLIDES {
+            let sweep = self.sweep(world, remaining);
+            self.advance(remaining, sweep.as_ref());
+            let Some(sweep) = sweep else {
+                return;
+            };
+            let bounds = self.physics.hull().aabb();
+            let velocity = self.physics.velocity;
+            hits.push(Hit { key: sweep.key, normal: sweep.normal, bounds, velocity });
+            remaining *= 1.0 - sweep.fraction;
+
+            let mut normal = sweep.normal;
+            if grounded && !self.is_walkable(normal) {
+                if self.step_up(world, remaining) {
+                    return;
+                }
+                let flat = Vector3::new(normal.x, 0.0, normal.z);
+                if flat.magnitude2() > 0.0 {
+                    normal = flat.normalize();
+                }
+            }
+            remove_into(&mut remaining, normal);
+            remove_into(&mut self.physics.velocity, normal);
+        }
+    }
+    // Tries to climb over whatever is in the way of moving by `delta`: up by the step height,
+    // across, and back down onto walkable ground. Stays put and returns false if that doesn't
+    // work.
+    fn step_up<W: World>(&mut self, world: &W, delta: Vector3<f32>) -> bool {
+        let start = self.physics.position;
+        let up = Vector3::new(0.0, self.step_height, 0.0);
+        self.advance(up, self.sweep(world, up).as_ref());
+        let climbed = self.physics.position - start;
+
+        let across = Vector3::new(delta.x, 0.0, delta.z);
+        let sweep = self.sweep(world, across);
+        let fraction = sweep.as_ref().map_or(1.0, |sweep| sweep.fraction);
+        if (across * fraction).magnitude() > MIN_STEP_PROGRESS {
+            self.advance(across, sweep.as_ref());
+            match self.sweep(world, -climbed) {
+                Some(down) if self.is_walkable(down.normal) => {
+                    self.settle(-climbed, &down);
+                    return true;
+                }
+                _ => {}
+            }
+        }
+        self.physics.position = start;
+        false
+    }
+    // The walkable ground under the character. Ground up to the step height down counts if it
+    // `was_grounded`, so that it follows steps and slopes down; the character moves down onto it.
+    fn find_ground<W: World>(&mut self, world: &W, was_grounded: bool) -> Option<Vector3<f32>> {
+        let depth = if was_grounded { self.step_height.max(GROUND_PROBE) } else { GROUND_PROBE };
+        let down = Vector3::new(0.0, -depth, 0.0);
+        let sweep = self.sweep(world, down)?;
+        if self.is_walkable(sweep.normal) {
+            self.settle(down, &sweep);
+            remove_into(&mut self.physics.velocity, sweep.normal);
+            return Some(sweep.normal);
+        }
+        // Steep ground it's touching still holds it up if something else stops it sliding down,
+        // like the other side of a V, and the two together are no steeper than the max slope.
+        if sweep.fraction * depth > GROUND_PROBE {
+            return None;
+        }
+        let mut slide = down;
+        remove_into(&mut slide, sweep.normal);
+        if slide.magnitude2() == 0.0 {
+            return None;
+        }
+        let other = self.sweep(world, slide.normalize_to(GROUND_PROBE))?;
+        let normal = (sweep.normal + other.normal).normalize();
+        if !self.is_walkable(normal) {
+            return None;
+        }
+        remove_into(&mut self.physics.velocity, sweep.normal);
+        remove_into(&mut self.physics.velocity, other.normal);
+        Some(normal)
+    }
+}
+
+// Takes away the part of `vector` that goes into a surface facing `normal`.
+fn remove_into(vector: &mut Vector3<f32>, normal: Vector3<f32>) {
+    let into = vector.dot(normal);
+    if into < 0.0 {
+        *vector -= into * normal;
+    }
+}
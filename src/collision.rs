@@ -0,0 +1,527 @@
+/* Collision: narrow-phase tests between convex shapes. A shape is described only by its support
+ * function, the point of the shape furthest along a given direction, which is all that GJK and
+ * EPA need. GJK decides whether two shapes overlap by searching their Minkowski difference for
+ * the origin; when they do, EPA expands the simplex GJK ends on until it finds the face of the
+ * difference nearest the origin, which gives the contact normal and how deep the shapes overlap.
+ */
+use cgmath::{InnerSpace, Vector3, Zero};
+
+use crate::{
+    game_state::Instance,
+    physics::{ColliderKind, Collision},
+};
+
+// Gives up on shapes that don't converge, which only happens with degenerate ones.
+const MAX_GJK_ITERATIONS: usize = 64;
+const MAX_EPA_ITERATIONS: usize = 64;
+// How close EPA has to get to the nearest face before it stops.
+const EPA_TOLERANCE: f32 = 0.0001;
+
+pub trait Convex {
+    // The point of the shape furthest along `direction`, which doesn't have to be normalized.
+    fn support(&self, direction: Vector3<f32>) -> Vector3<f32>;
+}
+
+// A model's collision shape where an instance puts it: scaled, then rotated, then moved.
+#[derive(Clone, Copy)]
+pub struct Placed<'a> {
+    pub collision: &'a Collision,
+    pub instance: &'a Instance,
+}
+impl Placed<'_> {
+    fn place(&self, point: Vector3<f32>) -> Vector3<f32> {
+        self.instance.rotation.rotate_vector(point * self.instance.scale) + self.instance.position
+    }
+    // The shape's vertices in world space.
+    pub fn vertices(&self) -> impl Iterator<Item = Vector3<f32>> + '_ {
+        self.collision.vertices.iter().map(|&vertex| self.place(vertex))
+    }
+    // The box around the rotated bounds, which is looser than the box around the rotated shape
+    // but doesn't need every vertex.
+    pub fn aabb(&self) -> Aabb {
+        let Aabb { min, max } = self.collision.bounds;
+        let corners = (0..8).map(|i| {
+            let pick = |bit: usize, min: f32, max: f32| if i & bit == 0 { min } else { max };
+            Vector3::new(pick(1, min.x, max.x), pick(2, min.y, max.y), pick(4, min.z, max.z))
+        });
+        Aabb::from_points(corners.map(|corner| self.place(corner)))
+    }
+    // The center and radius of a sphere around the shape.
+    pub fn bounding_sphere(&self) -> (Vector3<f32>, f32) {
+        (self.place(self.collision.center()), self.collision.radius * self.instance.scale)
+    }
+    // The contact that would move `shape`, which fits in `bounds`, out of this one.
+    pub fn push_out(&self, shape: &impl Convex, bounds: &Aabb) -> Option<Contact> {
+        if !self.may_overlap(bounds) {
+            return None;
+        }
+        match self.collision.kind {
+            ColliderKind::Hull => contact(shape, self),
+            ColliderKind::Mesh => self
+                .triangles(bounds)
+                .filter_map(|triangle| contact(shape, &triangle))
+                .max_by(|a, b| a.depth.total_cmp(&b.depth)),
+            ColliderKind::None => None,
+        }
+    }
+    // Like push_out(), but only says whether there would be a contact, which is cheaper.
+    pub fn overlaps(&self, shape: &impl Convex, bounds: &Aabb) -> bool {
+        if !self.may_overlap(bounds) {
+            return false;
+        }
+        match self.collision.kind {
+            ColliderKind::Hull => overlaps(shape, self),
+            ColliderKind::Mesh => self.triangles(bounds).any(|triangle| overlaps(shape, &triangle)),
+            ColliderKind::None => false,
+        }
+    }
+    // Whether the bounding sphere reaches `bounds`.
+    fn may_overlap(&self, bounds: &Aabb) -> bool {
+        let (center, radius) = self.bounding_sphere();
+        let nearest = Vector3::new(
+            center.x.clamp(bounds.min.x, bounds.max.x),
+            center.y.clamp(bounds.min.y, bounds.max.y),
+            center.z.clamp(bounds.min.z, bounds.max.z),
+        );
+        (nearest - center).magnitude2() <= radius * radius
+    }
+    // The triangles of a mesh collider whose boxes overlap `bounds`, in world space.
+    fn triangles<'a>(&'a self, bounds: &'a Aabb) -> impl Iterator<Item = Triangle> + 'a {
+        self.collision
+            .indices
+            .chunks_exact(3)
+            .map(|triangle| {
+                let corner = |k: usize| self.collision.vertices[triangle[k] as usize];
+                Triangle([0, 1, 2].map(|k| self.place(corner(k))))
+            })
+            .filter(|triangle| triangle.aabb().overlaps(bounds))
+    }
+}
+impl Convex for Placed<'_> {
+    fn support(&self, direction: Vector3<f32>) -> Vector3<f32> {
+        // Scaling doesn't change which vertex is furthest, as long as it's positive.
+        let local = self.instance.rotation.inverse().rotate_vector(direction);
+        let hull = Hull { points: &self.collision.vertices, offset: Vector3::zero() };
+        self.place(hull.support(local))
+    }
+}
+
+// A single triangle of a mesh collider, in world space.
+#[derive(Clone, Copy, Debug)]
+pub struct Triangle(pub [Vector3<f32>; 3]);
+impl Triangle {
+    pub fn aabb(&self) -> Aabb {
+        Aabb::from_points(self.0)
+    }
+}
+impl Convex for Triangle {
+    fn support(&self, direction: Vector3<f32>) -> Vector3<f32> {
+        Hull { points: &self.0, offset: Vector3::zero() }.support(direction)
+    }
+}
+
+// The convex hull of a set of points, moved by `offset`.
+#[derive(Clone, Copy, Debug)]
+pub struct Hull<'a> {
+    pub points: &'a [Vector3<f32>],
+    pub offset: Vector3<f32>,
+}
+impl Hull<'_> {
+    pub fn aabb(&self) -> Aabb {
+        Aabb::from_points(self.points.iter().map(|point| point + self.offset))
+    }
+}
+impl Convex for Hull<'_> {
+    fn support(&self, direction: Vector3<f32>) -> Vector3<f32> {
+        let furthest = self.points.iter().copied().max_by(|a, b| {
+            a.dot(direction).partial_cmp(&b.dot(direction)).unwrap_or(std::cmp::Ordering::Equal)
+        });
+        furthest.unwrap_or_else(Vector3::zero) + self.offset
+    }
+}
+
+// An axis-aligned bounding box, for ruling out pairs before trying the exact test.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Vector3<f32>,
+    pub max: Vector3<f32>,
+}
+impl Aabb {
+    // The box around some points, or an empty box at the origin if there are none.
+    pub fn from_points(points: impl IntoIterator<Item = Vector3<f32>>) -> Self {
+        let mut points = points.into_iter();
+        let Some(first) = points.next() else {
+            return Aabb { min: Vector3::zero(), max: Vector3::zero() };
+        };
+        points.fold(Aabb { min: first, max: first }, |aabb, point| Aabb {
+            min: Vector3::new(
+                aabb.min.x.min(point.x),
+                aabb.min.y.min(point.y),
+                aabb.min.z.min(point.z),
+            ),
+            max: Vector3::new(
+                aabb.max.x.max(point.x),
+                aabb.max.y.max(point.y),
+                aabb.max.z.max(point.z),
+            ),
+        })
+    }
+    pub fn overlaps(&self, other: &Aabb) -> bool {
+        self.min.x <= other.max.x
+            && other.min.x <= self.max.x
+            && self.min.y <= other.max.y
+            && other.min.y <= self.max.y
+            && self.min.z <= other.max.z
+            && other.min.z <= self.max.z
+    }
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb::from_points([self.min, self.max, other.min, other.max])
+    }
+    // The box where the two overlap, which is inside out if they don't.
+    pub fn intersection(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: Vector3::new(
+                self.min.x.max(other.min.x),
+                self.min.y.max(other.min.y),
+                self.min.z.max(other.min.z),
+            ),
+            max: Vector3::new(
+                self.max.x.min(other.max.x),
+                self.max.y.min(other.max.y),
+                self.max.z.min(other.max.z),
+            ),
+        }
+    }
+    pub fn center(&self) -> Vector3<f32> {
+        (self.min + self.max) / 2.0
+    }
+    pub fn contains(&self, other: &Aabb) -> bool {
+        self.min.x <= other.min.x
+            && self.min.y <= other.min.y
+            && self.min.z <= other.min.z
+            && other.max.x <= self.max.x
+            && other.max.y <= self.max.y
+            && other.max.z <= self.max.z
+    }
+    pub fn expanded(&self, margin: f32) -> Aabb {
+        let margin = Vector3::new(margin, margin, margin);
+        Aabb { min: self.min - margin, max: self.max + margin }
+    }
+    pub fn surface_area(&self) -> f32 {
+        let size = self.max - self.min;
+        2.0 * (size.x * size.y + size.y * size.z + size.z * size.x)
+    }
+}
+
+// How two overlapping shapes touch.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Contact {
+    // The unit direction to move the first shape in to separate it from the second.
+    pub normal: Vector3<f32>,
+    // How far it has to move.
+    pub depth: f32,
+}
+
+// Where `a` and `b` overlap, or None if they don't. Shapes that only touch don't overlap.
+pub fn contact(a: &impl Convex, b: &impl Convex) -> Option<Contact> {
+    let simplex = gjk(a, b)?;
+    epa(a, b, simplex)
+}
+
+// Whether `a` and `b` overlap, without working out how.
+pub fn overlaps(a: &impl Convex, b: &impl Convex) -> bool {
+    gjk(a, b).is_some()
+}
+
+// Whether `point` is inside `shape`.
+pub fn contains(shape: &impl Convex, point: Vector3<f32>) -> bool {
+    overlaps(&Hull { points: &[point], offset: Vector3::zero() }, shape)
+}
+
+// The support point of the Minkowski difference a - b.
+fn support(a: &impl Convex, b: &impl Convex, direction: Vector3<f32>) -> Vector3<f32> {
+    a.support(direction) - b.support(-direction)
+}
+
+// Up to four points of the Minkowski difference, newest first.
+struct Simplex {
+    points: [Vector3<f32>; 4],
+    len: usize,
+}
+impl Simplex {
+    fn push_front(&mut self, point: Vector3<f32>) {
+        self.points = [point, self.points[0], self.points[1], self.points[2]];
+        self.len = (self.len + 1).min(4);
+    }
+    fn set(&mut self, points: &[Vector3<f32>]) {
+        self.points[..points.len()].copy_from_slice(points);
+        self.len = points.len();
+    }
+}
+
+fn same_direction(a: Vector3<f32>, b: Vector3<f32>) -> bool {
+    a.dot(b) > 0.0
+}
+
+// Returns a tetrahedron of the Minkowski difference that encloses the origin, if there is one.
+fn gjk(a: &impl Convex, b: &impl Convex) -> Option<Simplex> {
+    let first = support(a, b, Vector3::unit_x());
+    let mut simplex = Simplex { points: [first; 4], len: 1 };
+    let mut direction = -first;
+    for _ in 0..MAX_GJK_ITERATIONS {
+        let point = support(a, b, direction);
+        // The difference doesn't reach past the origin in this direction, so can't contain it.
+        if point.dot(direction) <= 0.0 {
+            return None;
+        }
+        simplex.push_front(point);
+        if next_simplex(&mut simplex, &mut direction) {
+            return Some(simplex);
+        }
+    }
+    None
+}
+
+// Reduces the simplex to the part nearest the origin and points `direction` at the origin from it.
+// Returns whether the simplex is a tetrahedron that encloses the origin.
+fn next_simplex(simplex: &mut Simplex, direction: &mut Vector3<f32>) -> bool {
+    match simplex.len {
+        2 => line(simplex, direction),
+        3 => triangle(simplex, direction),
+        _ => tetrahedron(simplex, direction),
+    }
+}
+
+fn line(simplex: &mut Simplex, direction: &mut Vector3<f32>) -> bool {
+    let [a, b, ..] = simplex.points;
+    let (ab, ao) = (b - a, -a);
+    if same_direction(ab, ao) {
+        *direction = ab.cross(ao).cross(ab);
+        if direction.magnitude2() == 0.0 {
+            // The origin is on the line; any perpendicular will do.
+            *direction = ab.cross(Vector3::unit_x());
+            if direction.magnitude2() == 0.0 {
+                *direction = ab.cross(Vector3::unit_y());
+            }
+        }
+    } else {
+        simplex.set(&[a]);
+        *direction = ao;
+    }
+    false
+}
+
+fn triangle(simplex: &mut Simplex, direction: &mut Vector3<f32>) -> bool {
+    let [a, b, c, _] = simplex.points;
+    let (ab, ac, ao) = (b - a, c - a, -a);
+    let abc = ab.cross(ac);
+    if same_direction(abc.cross(ac), ao) {
+        if same_direction(ac, ao) {
+            simplex.set(&[a, c]);
+            *direction = ac.cross(ao).cross(ac);
+            return false;
+        }
+        simplex.set(&[a, b]);
+        return line(simplex, direction);
+    }
+    if same_direction(ab.cross(abc), ao) {
+        simplex.set(&[a, b]);
+        return line(simplex, direction);
+    }
+    if same_direction(abc, ao) {
+        *direction = abc;
+    } else {
+        simplex.set(&[a, c, b]);
+        *direction = -abc;
+    }
+    false
+}
+
+fn tetrahedron(simplex: &mut Simplex, direction: &mut Vector3<f32>) -> bool {
+    let [a, b, c, d] = simplex.points;
+    let (ab, ac, ad, ao) = (b - a, c - a, d - a, -a);
+    if same_direction(ab.cross(ac), ao) {
+        simplex.set(&[a, b, c]);
+        return triangle(simplex, direction);
+    }
+    if same_direction(ac.cross(ad), ao) {
+        simplex.set(&[a, c, d]);
+        return triangle(simplex, direction);
+    }
+    if same_direction(ad.cross(ab), ao) {
+        simplex.set(&[a, d, b]);
+        return triangle(simplex, direction);
+    }
+    true
+}
+
+struct Face {
+    indices: [usize; 3],
+    // Points out of the polytope.
+    normal: Vector3<f32>,
+    // From the origin to the face's plane.
+    distance: f32,
+}
+impl Face {
+    // None if the face has no area, which only happens when the polytope is degenerate. `inside`
+    // is a point inside the polytope to face away from. The origin won't do: it can be so close to
+    // a face that rounding puts it on the wrong side.
+    fn new(polytope: &[Vector3<f32>], indices: [usize; 3], inside: Vector3<f32>) -> Option<Face> {
+        let [a, b, c] = indices.map(|i| polytope[i]);
+        let normal = (b - a).cross(c - a);
+        if normal.magnitude2() < f32::EPSILON * f32::EPSILON {
+            return None;
+        }
+        let normal = normal.normalize();
+        let normal = if normal.dot(a - inside) < 0.0 { -normal } else { normal };
+        Some(Face { indices, normal, distance: normal.dot(a) })
+    }
+}
+
+// Expands GJK's tetrahedron into a polytope whose nearest face to the origin lies on the
+// boundary of the Minkowski difference.
+fn epa(a: &impl Convex, b: &impl Convex, simplex: Simplex) -> Option<Contact> {
+    let mut polytope = simplex.points.to_vec();
+    // The polytope only grows, so the middle of the tetrahedron stays inside it.
+    let inside = polytope.iter().sum::<Vector3<f32>>() / 4.0;
+    let mut faces: Vec<Face> = [[0, 1, 2], [0, 3, 1], [0, 2, 3], [1, 3, 2]]
+        .into_iter()
+        .filter_map(|indices| Face::new(&polytope, indices, inside))
+        .collect();
+    for _ in 0..MAX_EPA_ITERATIONS {
+        let nearest = faces.iter().min_by(|f, g| f.distance.total_cmp(&g.distance))?;
+        let (normal, distance) = (nearest.normal, nearest.distance);
+        let point = support(a, b, normal);
+        if point.dot(normal) - distance < EPA_TOLERANCE {
+            return Some(Contact { normal: -normal, depth: distance });
+        }
+        // Remove every face the new point can see, remembering the edges of the hole that leaves.
+        let mut edges: Vec<(usize, usize)> = vec![];
+        faces.retain(|face| {
+            if face.normal.dot(point - polytope[face.indices[0]]) <= 0.0 {
+                return true;
+            }
+            let [i, j, k] = face.indices;
+            for edge in [(i, j), (j, k), (k, i)] {
+                // An edge shared by two removed faces is inside the hole.
+                match edges.iter().position(|&e| e == (edge.1, edge.0)) {
+                    Some(index) => {
+                        edges.swap_remove(index);
+                    }
+                    None => edges.push(edge),
+                }
+            }
+            false
+        });
+        // Patch the hole with faces that fan out from the new point.
+        polytope.push(point);
+        let new_index = polytope.len() - 1;
+        faces.extend(
+            edges.iter().filter_map(|&(i, j)| Face::new(&polytope, [i, j, new_index], inside)),
+        );
+    }
+    let nearest = faces.iter().min_by(|f, g| f.distance.total_cmp(&g.distance))?;
+    Some(Contact { normal: -nearest.normal, depth: nearest.distance })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{game_state::Shader, rotor::Rotor};
+
+    fn unit_cube() -> Collision {
+        let corners = (0..8).map(|i| {
+            let pick = |bit: usize| if i & bit == 0 { -1.0 } else { 1.0 };
+            Vector3::new(pick(1), pick(2), pick(4))
+        });
+        Collision::new(corners.collect(), vec![])
+    }
+
+    fn instance(x: f32, y: f32, z: f32, scale: f32) -> Instance {
+        Instance {
+            position: Vector3::new(x, y, z),
+            scale,
+            rotation: Rotor::identity(),
+            shader: Shader::Texture,
+        }
+    }
+
+    #[test]
+    fn boxes() {
+        let cube = unit_cube();
+        let place = |instance| Placed { collision: &cube, instance };
+        let (a, b) = (instance(1.1, 0.0, 0.0, 0.5), instance(0.0, 0.0, 0.0, 0.5));
+        assert_eq!(contact(&place(&a), &place(&b)), None);
+
+        let a = instance(0.9, 0.2, 0.0, 0.5);
+        let touching = contact(&place(&a), &place(&b)).unwrap();
+        assert!((touching.normal - Vector3::unit_x()).magnitude() < 0.001);
+        assert!((touching.depth - 0.1).abs() < 0.001);
+
+        // A box resting on a box turned 45 degrees about z is held up by the ridge along its top.
+        let diamond = Instance {
+            rotation: Rotor::from_axis_angle(Vector3::unit_z(), cgmath::Deg(45.0)),
+            ..instance(0.0, 0.0, 0.0, 1.0)
+        };
+        let top = 2.0f32.sqrt();
+        let a = instance(0.0, top + 0.45, 0.0, 0.5);
+        let resting = contact(&place(&a), &place(&diamond)).unwrap();
+        assert!((resting.normal - Vector3::unit_y()).magnitude() < 0.001);
+        assert!((resting.depth - 0.05).abs() < 0.001);
+        // A stick poking into the ridge comes out of the nearest side, not the top.
+        let points = [Vector3::new(0.0, top - 0.4, 0.0), Vector3::new(0.0, top + 1.0, 0.0)];
+        let hull = Hull { points: &points, offset: Vector3::zero() };
+        let stick = place(&diamond).push_out(&hull, &hull.aabb()).unwrap();
+        assert!((stick.depth - 0.4 / 2.0f32.sqrt()).abs() < 0.001);
+        assert!((stick.normal.y - 0.5f32.sqrt()).abs() < 0.001);
+        assert!(place(&diamond).aabb().overlaps(&hull.aabb()));
+        assert!((place(&diamond).aabb().max.y - top).abs() < 0.001);
+    }
+
+    #[test]
+    fn barely_overlapping_faces_push_apart_the_short_way() {
+        // Two boxes from a settled stack, a hair into each other and turned a hair apart. The
+        // origin is all but on a face of GJK's tetrahedron.
+        let cube = unit_cube();
+        let lower = Instance {
+            rotation: Rotor::new(1.0, -1.2380056e-5, -2.6363801e-5, 7.526333e-5),
+            ..instance(2.9999528, -4.000232, -0.00014978705, 0.5)
+        };
+        let upper = Instance {
+            rotation: Rotor::new(1.0, -1.5469139e-5, -2.7890326e-5, 8.077769e-5),
+            ..instance(2.9999244, -3.0002425, -0.00032581046, 0.5)
+        };
+        let place = |instance| Placed { collision: &cube, instance };
+        let contact = contact(&place(&lower), &place(&upper)).unwrap();
+        assert!((contact.normal + Vector3::unit_y()).magnitude() < 0.001, "{contact:?}");
+        assert!(contact.depth < 0.001, "{contact:?}");
+    }
+
+    #[test]
+    fn mesh_colliders_are_hollow() {
+        // The cube's faces, two triangles each.
+        let faces =
+            [[0, 2, 6, 4], [1, 3, 7, 5], [0, 1, 5, 4], [2, 3, 7, 6], [0, 1, 3, 2], [4, 5, 7, 6]];
+        let indices = faces.iter().flat_map(|[a, b, c, d]| [*a, *b, *c, *a, *c, *d]).collect();
+        let hollow =
+            Collision { kind: ColliderKind::Mesh, ..Collision::new(unit_cube().vertices, indices) };
+        let solid = unit_cube();
+        let big = instance(0.0, 0.0, 0.0, 2.0);
+
+        let corners: Vec<Vector3<f32>> = unit_cube().vertices.iter().map(|v| v * 0.25).collect();
+        let inside = Hull { points: &corners, offset: Vector3::zero() };
+        let hollow_push =
+            Placed { collision: &hollow, instance: &big }.push_out(&inside, &inside.aabb());
+        assert_eq!(hollow_push, None);
+        assert!(Placed { collision: &solid, instance: &big }
+            .push_out(&inside, &inside.aabb())
+            .is_some());
+
+        let through_top = Hull { offset: Vector3::new(0.0, 2.1, 0.0), ..inside };
+        let contact = Placed { collision: &hollow, instance: &big }
+            .push_out(&through_top, &through_top.aabb())
+            .unwrap();
+        assert!((contact.normal - Vector3::unit_y()).magnitude() < 0.001);
+        assert!((contact.depth - 0.15).abs() < 0.001);
+    }
+}
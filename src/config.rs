@@ -0,0 +1,387 @@
+/* Config: startup settings, read from a config file and then overridden by command-line flags.
+ * The file is assets/config.cfg unless --config <path> names another, and has one setting per
+ * line:
+ *
+ *   <key> = <value>
+ *
+ * '#' starts a comment. Every key can also be given on the command line as --<key> <value>, with
+ * dashes in place of underscores (--tick-rate 60). --windowed, --borderless and --fullscreen are
+ * short for --window-mode <mode>.
+ *
+ *   scene              scene file in assets/                           default.scene
+ *   bindings           bindings file in assets/                        bindings.cfg
+ *   width, height      window size in pixels, and the display mode in fullscreen   2560, 1440
+ *   window_mode        windowed, borderless or fullscreen; F11 toggles windowed    windowed
+ *   present_mode       auto_vsync, auto_no_vsync, fifo, mailbox or immediate   auto_vsync
+ *   vsync              shorthand for auto_vsync (true) or auto_no_vsync (false)
+ *   backend            auto, vulkan, metal, dx12, dx11 or gl           auto
+ *   tick_rate          game updates per second                         100
+ *   max_fps            frames per second the renderer is capped at     100
+ *   player_force       how hard the player pushes when moving          6
+ *   gravity            vertical acceleration, negative is down         -9
+ *   max_slope          steepest ground the player can stand on, in degrees   45
+ *   step_height        tallest step the player walks up without jumping      0.25
+ *   fov                vertical field of view in degrees, overriding the scene's
+ *   mouse_sensitivity  degrees per pixel, overriding the bindings file's
+ *   capture_dir        where screenshots and recordings go             captures
+ *   record             record this many frames from startup            0
+ *   record_fps         recorded frames per second of game time, rounded to whole ticks   50
+ *   headless           <width>x<height>; render offscreen without a window, one frame unless
+ *                      record says otherwise
+ *   record_input       write every tick's input to this file
+ *   replay             check an input recording against the simulation instead of playing
+ */
+use crate::{
+    capture::CaptureOptions,
+    constants::{
+        DEFAULT_GRAVITY, DEFAULT_MAX_RENDER_FPS, DEFAULT_MAX_SLOPE, DEFAULT_PLAYER_FORCE,
+        DEFAULT_STEP_HEIGHT, DEFAULT_TICKS_PER_SECOND, DEFAULT_WINDOW_HEIGHT, DEFAULT_WINDOW_WIDTH,
+    },
+    platform::WindowMode,
+    resources::{self, ParseError},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use std::{path::PathBuf, str::FromStr, time::Duration};
+
+const DEFAULT_CONFIG: &str = "config.cfg";
+
+// Ticks are timed in whole nanoseconds, so any faster and a tick would take no time at all.
+pub const MAX_TICKS_PER_SECOND: f32 = 1e9;
+
+// The numbers that decide how the game plays. Replays record these, since changing any of them
+// changes where the player ends up.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tuning {
+    pub ticks_per_second: f32,
+    pub player_force: f32,
+    pub gravity: f32,
+    // In degrees from flat.
+    pub max_slope: f32,
+    pub step_height: f32,
+}
+impl Default for Tuning {
+    fn default() -> Self {
+        Tuning {
+            ticks_per_second: DEFAULT_TICKS_PER_SECOND,
+            player_force: DEFAULT_PLAYER_FORCE,
+            gravity: DEFAULT_GRAVITY,
+            max_slope: DEFAULT_MAX_SLOPE,
+            step_height: DEFAULT_STEP_HEIGHT,
+        }
+    }
+}
+impl Tuning {
+    pub fn time_per_tick(&self) -> Duration {
+        Duration::from_nanos((1_000_000_000.0 / self.ticks_per_second) as u64)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GraphicsConfig {
+    pub present_mode: wgpu::PresentMode,
+    pub backends: wgpu::Backends,
+}
+impl Default for GraphicsConfig {
+    fn default() -> Self {
+        GraphicsConfig {
+            present_mode: wgpu::PresentMode::AutoVsync,
+            backends: wgpu::Backends::all(),
+        }
+    }
+}
+
+pub struct Config {
+    pub scene: String,
+    pub bindings: String,
+    pub width: i32,
+    pub height: i32,
+    pub window_mode: WindowMode,
+    pub graphics: GraphicsConfig,
+    pub max_fps: f32,
+    pub tuning: Tuning,
+    pub fov: Option<f32>,
+    pub mouse_sensitivity: Option<f32>,
+    pub capture: CaptureOptions,
+}
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            scene: "default.scene".to_string(),
+            bindings: "bindings.cfg".to_string(),
+            width: DEFAULT_WINDOW_WIDTH,
+            height: DEFAULT_WINDOW_HEIGHT,
+            window_mode: WindowMode::Windowed,
+            graphics: GraphicsConfig::default(),
+            max_fps: DEFAULT_MAX_RENDER_FPS,
+            tuning: Tuning::default(),
+            fov: None,
+            mouse_sensitivity: None,
+            capture: CaptureOptions::default(),
+        }
+    }
+}
+impl Config {
+    // Reads the config file, then applies the flags in `args` on top of it.
+    pub async fn load(args: impl Iterator<Item = String>) -> Result<Self> {
+        let args: Vec<String> = args.collect();
+        let mut config = Config::default();
+        // The file is read before any other flag is applied, so that flags win.
+        match args.iter().position(|arg| arg == "--config") {
+            Some(index) => {
+                let path = args.get(index + 1).ok_or_else(|| anyhow!("--config needs a value"))?;
+                let text = std::fs::read_to_string(path)
+                    .with_context(|| format!("Could not read config file {path}"))?;
+                config.parse(path, &text)?;
+            }
+            None => {
+                let text = resources::load_string(DEFAULT_CONFIG).await?;
+                config.parse(DEFAULT_CONFIG, &text)?;
+            }
+        }
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or_else(|| anyhow!("{arg} needs a value"));
+            match arg.as_str() {
+                "--config" => {
+                    value()?;
+                }
+                "--windowed" => config.window_mode = WindowMode::Windowed,
+                "--borderless" => config.window_mode = WindowMode::Borderless,
+                "--fullscreen" => config.window_mode = WindowMode::Fullscreen,
+                flag if flag.starts_with("--") => {
+                    let key = flag[2..].replace('-', "_");
+                    if !is_key(&key) {
+                        bail!("Unknown option {flag}");
+                    }
+                    config.set(&key, &value()?).map_err(|e| anyhow!("{arg}: {e}"))?;
+                }
+                _ => bail!("Unexpected argument {arg}"),
+            }
+        }
+        if config.capture.headless.is_some() && config.capture.record_frames == 0 {
+            config.capture.record_frames = 1;
+        }
+        Ok(config)
+    }
+    // Applies the settings in a config file. file_name is only used for error messages.
+    pub fn parse(&mut self, file_name: &str, text: &str) -> Result<(), ParseError> {
+        for (index, line) in text.lines().enumerate() {
+            let error = |message: String| ParseError {
+                file_name: file_name.to_string(),
+                line: index + 1,
+                message,
+            };
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(error("Expected '<key> = <value>'".to_string()));
+            };
+            let key = key.trim();
+            if !is_key(key) {
+                return Err(error(format!("Unknown setting '{key}'")));
+            }
+            self.set(key, value.trim()).map_err(error)?;
+        }
+        Ok(())
+    }
+    fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "scene" => self.scene = value.to_string(),
+            "bindings" => self.bindings = value.to_string(),
+            "width" => self.width = positive(key, value)?,
+            "height" => self.height = positive(key, value)?,
+            "window_mode" => {
+                self.window_mode = WindowMode::from_name(value)
+                    .ok_or_else(|| format!("Unknown window mode '{value}'"))?
+            }
+            "present_mode" => {
+                self.graphics.present_mode = match value {
+                    "auto_vsync" => wgpu::PresentMode::AutoVsync,
+                    "auto_no_vsync" => wgpu::PresentMode::AutoNoVsync,
+                    "fifo" => wgpu::PresentMode::Fifo,
+                    "mailbox" => wgpu::PresentMode::Mailbox,
+                    "immediate" => wgpu::PresentMode::Immediate,
+                    _ => return Err(format!("Unknown present mode '{value}'")),
+                }
+            }
+            "vsync" => {
+                self.graphics.present_mode = if boolean(key, value)? {
+                    wgpu::PresentMode::AutoVsync
+                } else {
+                    wgpu::PresentMode::AutoNoVsync
+                }
+            }
+            "backend" => {
+                self.graphics.backends = match value {
+                    "auto" => wgpu::Backends::all(),
+                    "vulkan" => wgpu::Backends::VULKAN,
+                    "metal" => wgpu::Backends::METAL,
+                    "dx12" => wgpu::Backends::DX12,
+                    "dx11" => wgpu::Backends::DX11,
+                    "gl" => wgpu::Backends::GL,
+                    _ => return Err(format!("Unknown backend '{value}'")),
+                }
+            }
+            "tick_rate" => match positive_number(key, value)? {
+                rate if rate <= MAX_TICKS_PER_SECOND => self.tuning.ticks_per_second = rate,
+                _ => {
+                    return Err(format!(
+                        "tick_rate can't be over {MAX_TICKS_PER_SECOND}, found {value}"
+                    ))
+                }
+            },
+            "max_fps" => self.max_fps = positive_number(key, value)?,
+            "player_force" => self.tuning.player_force = number(key, value)?,
+            "gravity" => self.tuning.gravity = number(key, value)?,
+            "max_slope" => match number(key, value)? {
+                slope if (0.0..90.0).contains(&slope) => self.tuning.max_slope = slope,
+                _ => return Err(format!("max_slope must be under 90 degrees, found {value}")),
+            },
+            "step_height" => match number(key, value)? {
+                height if height >= 0.0 => self.tuning.step_height = height,
+                _ => return Err(format!("step_height can't be negative, found {value}")),
+            },
+            "fov" => match number(key, value)? {
+                fov if 0.0 < fov && fov < 180.0 => self.fov = Some(fov),
+                _ => return Err(format!("fov must be between 0 and 180 degrees, found {value}")),
+            },
+            "mouse_sensitivity" => self.mouse_sensitivity = Some(number(key, value)?),
+            "capture_dir" => self.capture.dir = PathBuf::from(value),
+            "record" => self.capture.record_frames = parse(key, value)?,
+            "record_fps" => self.capture.record_fps = positive_number(key, value)?,
+            "headless" => {
+                let size = value
+                    .split_once('x')
+                    .and_then(|(width, height)| Some((width.parse().ok()?, height.parse().ok()?)));
+                match size {
+                    Some((width, height)) if width > 0 && height > 0 => {
+                        self.capture.headless = Some((width, height))
+                    }
+                    _ => return Err(format!("headless expects <width>x<height>, found '{value}'")),
+                }
+            }
+            "record_input" => self.capture.record_input = Some(PathBuf::from(value)),
+            "replay" => self.capture.replay = Some(PathBuf::from(value)),
+            _ => unreachable!("{key} is missing from KEYS"),
+        }
+        Ok(())
+    }
+    pub fn time_per_render_frame(&self) -> Duration {
+        Duration::from_nanos((1_000_000_000.0 / self.max_fps) as u64)
+    }
+}
+
+const KEYS: [&str; 22] = [
+    "scene",
+    "bindings",
+    "width",
+    "height",
+    "window_mode",
+    "present_mode",
+    "vsync",
+    "backend",
+    "tick_rate",
+    "max_fps",
+    "player_force",
+    "gravity",
+    "max_slope",
+    "step_height",
+    "fov",
+    "mouse_sensitivity",
+    "capture_dir",
+    "record",
+    "record_fps",
+    "headless",
+    "record_input",
+    "replay",
+];
+
+fn is_key(key: &str) -> bool {
+    KEYS.contains(&key)
+}
+
+fn parse<T: FromStr>(key: &str, value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("{key} can't be '{value}'"))
+}
+
+fn number(key: &str, value: &str) -> Result<f32, String> {
+    match parse::<f32>(key, value)? {
+        number if number.is_finite() => Ok(number),
+        _ => Err(format!("{key} expects a number, found '{value}'")),
+    }
+}
+
+fn positive<T: FromStr + PartialOrd + Default>(key: &str, value: &str) -> Result<T, String> {
+    match parse::<T>(key, value)? {
+        number if number > T::default() => Ok(number),
+        _ => Err(format!("{key} must be positive, found '{value}'")),
+    }
+}
+
+fn positive_number(key: &str, value: &str) -> Result<f32, String> {
+    match number(key, value)? {
+        number if number > 0.0 => Ok(number),
+        _ => Err(format!("{key} must be positive, found '{value}'")),
+    }
+}
+
+fn boolean(key: &str, value: &str) -> Result<bool, String> {
+    match value {
+        "true" | "yes" | "on" => Ok(true),
+        "false" | "no" | "off" => Ok(false),
+        _ => Err(format!("{key} expects true or false, found '{value}'")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Result<Config, ParseError> {
+        let mut config = Config::default();
+        config.parse("test.cfg", text)?;
+        Ok(config)
+    }
+
+    #[test]
+    fn settings_override_the_defaults() {
+        let config =
+            parse("# Faster\ntick_rate = 60\n\nwindow_mode = borderless # for streaming\n")
+                .unwrap();
+        assert_eq!(config.tuning.ticks_per_second, 60.0);
+        assert_eq!(config.window_mode, WindowMode::Borderless);
+        assert_eq!(config.tuning.gravity, DEFAULT_GRAVITY);
+    }
+
+    #[test]
+    fn mistakes_are_reported_with_their_line() {
+        let error = |text| parse(text).err().map(|e| (e.line, e.message));
+        assert_eq!(
+            error("# Window\n\nwidht = 800"),
+            Some((3, "Unknown setting 'widht'".to_string()))
+        );
+        assert_eq!(
+            error("width = 800\nheight 600"),
+            Some((2, "Expected '<key> = <value>'".to_string()))
+        );
+        assert_eq!(error("width = 0"), Some((1, "width must be positive, found '0'".to_string())));
+        assert_eq!(error("gravity = down"), Some((1, "gravity can't be 'down'".to_string())));
+        assert_eq!(
+            error("max_fps = inf"),
+            Some((1, "max_fps expects a number, found 'inf'".to_string()))
+        );
+    }
+
+    #[test]
+    fn every_tick_takes_some_time() {
+        for rate in ["inf", "NaN", "0", "-5", "2e9"] {
+            assert!(parse(&format!("tick_rate = {rate}")).is_err(), "took tick_rate {rate}");
+        }
+        let config = parse(&format!("tick_rate = {MAX_TICKS_PER_SECOND}")).unwrap();
+        assert_eq!(config.tuning.time_per_tick(), Duration::from_nanos(1));
+    }
+}
@@ -1,11 +1,10 @@
-use std::{cell::LazyCell, time::Duration};
+// Defaults for the settings that config files and command-line flags can change. See config.rs.
+pub const DEFAULT_WINDOW_WIDTH: i32 = 2560;
+pub const DEFAULT_WINDOW_HEIGHT: i32 = 1440;
+pub const DEFAULT_TICKS_PER_SECOND: f32 = 100.0;
+pub const DEFAULT_MAX_RENDER_FPS: f32 = 100.0;
 
-const GAME_TICKS_PER_SECOND: f32 = 100.0;
-pub const TIME_PER_GAME_TICK: LazyCell<Duration> =
-    LazyCell::new(|| Duration::from_secs_f32(1.0 / GAME_TICKS_PER_SECOND));
-const MAX_RENDER_FPS: f32 = 100.0;
-pub const MIN_TIME_PER_RENDER_FRAME: LazyCell<Duration> =
-    LazyCell::new(|| Duration::from_secs_f32(1.0 / MAX_RENDER_FPS));
-
-pub const PLAYER_FORCE: f32 = 6.0;
-pub const GRAVITY: f32 = -9.0;
+pub const DEFAULT_PLAYER_FORCE: f32 = 6.0;
+pub const DEFAULT_GRAVITY: f32 = -9.0;
+pub const DEFAULT_MAX_SLOPE: f32 = 45.0;
+pub const DEFAULT_STEP_HEIGHT: f32 = 0.25;
@@ -0,0 +1,193 @@
+/* Events: the typed input and window events that the platform backends publish, and the bus that
+ * hands them to the GPU and game threads. Each subscriber gets its own channel and sees every
+ * event, in order. */
+use crate::platform::{Point, Rect};
+
+use std::sync::mpsc::{self, Receiver, Sender};
+
+// Keys by what they're labeled on a US layout, whatever the platform's own codes are.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Key {
+    Left,
+    Right,
+    Up,
+    Down,
+    Space,
+    Enter,
+    Escape,
+    Tab,
+    Backspace,
+    Shift,
+    Control,
+    Alt,
+    // 'A' through 'Z'.
+    Letter(char),
+    // 0 through 9.
+    Digit(u8),
+    // F1 through F12.
+    F(u8),
+}
+
+impl Key {
+    // Parses the names used in bindings files: letters, digits, F1 to F12, and the names of the
+    // variants above. Case doesn't matter.
+    pub fn from_name(name: &str) -> Option<Key> {
+        let upper = name.to_ascii_uppercase();
+        let key = match upper.as_str() {
+            "LEFT" => Key::Left,
+            "RIGHT" => Key::Right,
+            "UP" => Key::Up,
+            "DOWN" => Key::Down,
+            "SPACE" => Key::Space,
+            "ENTER" => Key::Enter,
+            "ESCAPE" => Key::Escape,
+            "TAB" => Key::Tab,
+            "BACKSPACE" => Key::Backspace,
+            "SHIFT" => Key::Shift,
+            "CONTROL" => Key::Control,
+            "ALT" => Key::Alt,
+            _ => {
+                let mut chars = upper.chars();
+                match (chars.next(), chars.as_str()) {
+                    (Some(c @ 'A'..='Z'), "") => Key::Letter(c),
+                    (Some(c @ '0'..='9'), "") => Key::Digit(c as u8 - b'0'),
+                    (Some('F'), number) => match number.parse() {
+                        Ok(n @ 1..=12) => Key::F(n),
+                        _ => return None,
+                    },
+                    _ => return None,
+                }
+            }
+        };
+        Some(key)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MouseButton {
+    Left,
+    Right,
+    Middle,
+}
+
+// Buttons by their position on an Xbox pad.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum GamepadButton {
+    A,
+    B,
+    X,
+    Y,
+    LeftShoulder,
+    RightShoulder,
+    LeftThumb,
+    RightThumb,
+    Start,
+    Back,
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+}
+impl GamepadButton {
+    pub const ALL: [GamepadButton; 14] = [
+        GamepadButton::A,
+        GamepadButton::B,
+        GamepadButton::X,
+        GamepadButton::Y,
+        GamepadButton::LeftShoulder,
+        GamepadButton::RightShoulder,
+        GamepadButton::LeftThumb,
+        GamepadButton::RightThumb,
+        GamepadButton::Start,
+        GamepadButton::Back,
+        GamepadButton::DPadUp,
+        GamepadButton::DPadDown,
+        GamepadButton::DPadLeft,
+        GamepadButton::DPadRight,
+    ];
+    // The names used in bindings files.
+    pub fn name(self) -> &'static str {
+        match self {
+            GamepadButton::A => "pad_a",
+            GamepadButton::B => "pad_b",
+            GamepadButton::X => "pad_x",
+            GamepadButton::Y => "pad_y",
+            GamepadButton::LeftShoulder => "pad_lb",
+            GamepadButton::RightShoulder => "pad_rb",
+            GamepadButton::LeftThumb => "pad_ls",
+            GamepadButton::RightThumb => "pad_rs",
+            GamepadButton::Start => "pad_start",
+            GamepadButton::Back => "pad_back",
+            GamepadButton::DPadUp => "pad_up",
+            GamepadButton::DPadDown => "pad_down",
+            GamepadButton::DPadLeft => "pad_left",
+            GamepadButton::DPadRight => "pad_right",
+        }
+    }
+}
+
+// Stick axes go from -1 to 1, with +x to the right and +y up. Triggers go from 0 to 1.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum GamepadAxis {
+    LeftX,
+    LeftY,
+    RightX,
+    RightY,
+    LeftTrigger,
+    RightTrigger,
+}
+impl GamepadAxis {
+    pub const ALL: [GamepadAxis; 6] = [
+        GamepadAxis::LeftX,
+        GamepadAxis::LeftY,
+        GamepadAxis::RightX,
+        GamepadAxis::RightY,
+        GamepadAxis::LeftTrigger,
+        GamepadAxis::RightTrigger,
+    ];
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Event {
+    // `repeat` is set for presses generated by OS auto-repeat while the key is held.
+    KeyPressed { key: Key, repeat: bool },
+    KeyReleased { key: Key },
+    MouseButtonPressed { button: MouseButton },
+    MouseButtonReleased { button: MouseButton },
+    GamepadButtonPressed { button: GamepadButton },
+    GamepadButtonReleased { button: GamepadButton },
+    // The raw position of the axis; dead zones are applied by whoever reads it.
+    GamepadAxisMoved { axis: GamepadAxis, value: f32 },
+    // Everything on the pad should be treated as released and centered.
+    GamepadDisconnected,
+    // Where the cursor is in the client area.
+    MouseMoved(Point),
+    // Raw mouse motion in device units, sent only while the window has grabbed the cursor.
+    MouseDelta { dx: f32, dy: f32 },
+    // The new client area.
+    Resized(Rect),
+    FocusLost,
+    Quit,
+}
+
+pub type EventReceiver = Receiver<Event>;
+
+#[derive(Default)]
+pub struct EventBus {
+    subscribers: Vec<Sender<Event>>,
+}
+impl EventBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    // Every event published from now on will be delivered to the returned receiver.
+    pub fn subscribe(&mut self) -> EventReceiver {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribers.push(sender);
+        receiver
+    }
+    pub fn publish(&mut self, event: Event) {
+        // Subscribers that hung up are dropped.
+        self.subscribers.retain(|subscriber| subscriber.send(event).is_ok());
+    }
+}
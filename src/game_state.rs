@@ -1,190 +1,200 @@
-use std::time::Instant;
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
-use cgmath::{num_traits::abs, InnerSpace, Vector3, Zero};
+use cgmath::{num_traits::abs, InnerSpace, Point3, Vector3, VectorSpace, Zero};
 
 use crate::{
+    broad_phase::BroadPhase,
     camera::Camera,
-    constants::{GRAVITY, PLAYER_FORCE, TIME_PER_GAME_TICK},
+    character::{Character, World},
+    collision::{Aabb, Contact, Hull, Placed},
+    config::Tuning,
     gpu_state::InstanceRaw,
-    physics::{Collision, Physics},
+    input::TickInput,
+    light::Light,
+    physics::ColliderKind,
+    rigid_body::{self, RigidBody, Solver, Touch},
     rotor::Rotor,
+    registry::{ModelHandle, ModelRegistry},
+    scene::Scene,
 };
 
 #[derive(Clone)]
 pub struct ModelWithInstances {
-    pub id: u32,
+    pub model: ModelHandle,
     pub instances: Vec<Instance>,
 }
 
+// The part of a GameState that the renderer needs. The game thread fills these in and hands them to
+// the GPU thread.
 #[derive(Clone)]
-struct Player {
-    camera: Camera,
-    physics: Physics,
+pub struct RenderSnapshot {
+    pub tick: isize,
+    pub update_instant: Instant,
+    // How much real time each tick took, which the slow-motion controls stretch.
+    pub tick_duration: Duration,
+    // Game time in seconds, which is what shaders animate by.
+    pub time: f32,
+    pub camera: Camera,
+    entities: Vec<ModelWithInstances>,
+}
+impl RenderSnapshot {
+    pub fn instances(&self, model: ModelHandle) -> &[Instance] {
+        self.entities.iter().find(|entity| entity.model == model).map_or(&[], |e| &e.instances)
+    }
+    // Like clone_from, but reuses the allocations already in self.
+    pub fn copy_from(&mut self, other: &RenderSnapshot) {
+        self.tick = other.tick;
+        self.update_instant = other.update_instant;
+        self.tick_duration = other.tick_duration;
+        self.time = other.time;
+        self.camera = other.camera;
+        copy_entities(&mut self.entities, &other.entities);
+    }
+    // Writes the snapshot to draw at `now`, given the two most recent ones, into `out`. Rendering
+    // runs up to a tick behind the simulation, so that there is always a later snapshot to blend
+    // toward; the blend factor is how far `now` is past `current`, as a fraction of the time
+    // between the two. That time is counted in ticks rather than from the update instants, so that
+    // a tick stepped after a long pause still blends over one tick.
+    pub fn interpolate(
+        previous: &RenderSnapshot,
+        current: &RenderSnapshot,
+        now: Instant,
+        out: &mut RenderSnapshot,
+    ) {
+        out.copy_from(current);
+        let ticks = (current.tick - previous.tick).max(0) as u32;
+        let blend_duration = current.tick_duration * ticks;
+        if blend_duration.is_zero() {
+            return;
+        }
+        let elapsed = now.saturating_duration_since(current.update_instant);
+        let t = (elapsed.as_secs_f32() / blend_duration.as_secs_f32()).min(1.0);
+
+        out.time = previous.time + (current.time - previous.time) * t;
+        out.camera = previous.camera.lerp(&current.camera, t);
+        for entity in &mut out.entities {
+            // Instances are matched up by index, so ones that were just spawned don't blend.
+            let old_instances = previous.instances(entity.model);
+            for (instance, old) in entity.instances.iter_mut().zip(old_instances) {
+                *instance = old.lerp(instance, t);
+            }
+        }
+    }
 }
 
-const CAMERA_PHYSICS_OFFSET: f32 = 0.4;
+// Copies src into dst, reusing dst's instance vecs. The derived clone_from would reallocate them.
+fn copy_entities(dst: &mut Vec<ModelWithInstances>, src: &[ModelWithInstances]) {
+    dst.truncate(src.len());
+    for (dst_entity, src_entity) in dst.iter_mut().zip(src) {
+        dst_entity.model = src_entity.model;
+        dst_entity.instances.clone_from(&src_entity.instances);
+    }
+    let copied = dst.len();
+    dst.extend_from_slice(&src[copied..]);
+}
+
+// Names an instance in the broad phase.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct InstanceId {
+    pub model: ModelHandle,
+    pub index: usize,
+}
+
+const JUMP_SPEED: f32 = 5.0;
 
 #[derive(Clone)]
 pub struct GameState {
-    player: Player,
+    player: Character,
     tick: isize,
-    update_instant: Instant,
-    pub instanced_entities: Vec<ModelWithInstances>,
-    // pub cube_instances: Vec<Instance>,
-    //pub simple_cube_instances: Vec<Instance>,
+    tuning: Tuning,
+    light: Light,
+    // Shared with every clone, since the set of models never changes after loading.
+    models: Arc<ModelRegistry>,
+    instanced_entities: Vec<ModelWithInstances>,
+    // Where every instance is. Bodies move theirs as they move.
+    broad_phase: BroadPhase<InstanceId>,
+    // The instances that physics moves. Each keeps its instance where it is every tick.
+    bodies: Vec<RigidBody>,
+    // Which body, by index, moves each instance that has one. Shared like models, since bodies
+    // come only from the scene.
+    body_indices: Arc<HashMap<InstanceId, usize>>,
+    solver: Solver,
 }
 impl GameState {
-    pub fn new(aspect_ratio: f32) -> Self {
-        let mut player_physics = Physics::new();
-        player_physics.collision = Collision::new(
-            [
-                Vector3::new(0.125, 0.125, 0.5),
-                Vector3::new(-0.125, 0.125, 0.5),
-                Vector3::new(0.125, -0.125, 0.5),
-                Vector3::new(-0.125, -0.125, 0.5),
-                Vector3::new(0.125, 0.125, -0.5),
-                Vector3::new(-0.125, 0.125, -0.5),
-                Vector3::new(0.125, -0.125, -0.5),
-                Vector3::new(-0.125, -0.125, -0.5),
-            ]
-            .into(),
-            [].into(),
-        );
-        let mut instanced_entities = Vec::<ModelWithInstances>::new();
-        const NUM_INSTANCES_PER_ROW: u32 = 10;
-        const INSTANCE_DISPLACEMENT: cgmath::Vector3<f32> = cgmath::Vector3::new(
-            NUM_INSTANCES_PER_ROW as f32 * 0.5,
-            0.0,
-            NUM_INSTANCES_PER_ROW as f32 * 0.5,
-        );
-        const SPACE_BETWEEN: f32 = 3.0;
-        let mut instances = (0..NUM_INSTANCES_PER_ROW)
-            .flat_map(|z| {
-                (0..NUM_INSTANCES_PER_ROW).map(move |x| {
-                    let position = (-SPACE_BETWEEN)
-                        * (cgmath::Vector3 { x: x as f32, y: 0.0, z: z as f32 }
-                            - INSTANCE_DISPLACEMENT);
-
-                    let rotation = if position.is_zero() {
-                        // this is needed so an object at (0, 0, 0) won't get scaled to zero
-                        // as Quaternions can affect scale if they're not created correctly
-                        Rotor::from_axis_angle(cgmath::Vector3::unit_z(), cgmath::Deg(0.0))
-                    } else {
-                        Rotor::from_axis_angle(
-                            position.normalize(),
-                            //cgmath::Deg(0.0),
-                            // cgmath::Deg(3.0 * ((x + 1) * (z + 1)) as f32),
-                            cgmath::Deg(45.0),
-                        )
-                    };
-                    Instance { position, scale: 1.0, rotation, shader: Shader::Texture }
-                })
+    pub fn from_scene(scene: &Scene, aspect_ratio: f32, tuning: Tuning) -> Self {
+        let instanced_entities = scene
+            .models
+            .handles()
+            .map(|model| ModelWithInstances {
+                model,
+                instances: scene
+                    .instances
+                    .iter()
+                    .filter(|(handle, _)| *handle == model)
+                    .map(|(_, instance)| *instance)
+                    .collect(),
             })
             .collect::<Vec<_>>();
-        // Big floor instance.
-        instances.push(Instance {
-            position: (0.0, -20.0, 0.0).into(),
-            scale: 11.0,
-            rotation: Rotor::identity(),
-            shader: Shader::Texture,
-        });
-        // Light instance.
-        instances.push(Instance {
-            position: Vector3::new(2.0, 2.0, 2.0),
-            scale: 0.25,
-            rotation: Rotor::identity(),
-            shader: Shader::NonMaterial,
-        });
-        instanced_entities.push(ModelWithInstances { id: 0, instances });
-        let simple_cube_instances = vec![
-            Instance {
-                position: (0.0, -4.5, 0.0).into(),
-                scale: 0.5,
-                rotation: Rotor::identity(),
-                shader: Shader::Pulse,
-            },
-            Instance {
-                position: (3.0, -4.5, 0.0).into(),
-                scale: 0.5,
-                rotation: Rotor::identity(),
-                shader: Shader::Ripple,
-            },
-            Instance {
-                position: (-3.0, -4.5, 0.0).into(),
-                scale: 0.5,
-                rotation: Rotor::identity(),
-                shader: Shader::ColorTween,
-            },
-            // Interesting "bug": the spheres don't show up through this cube, because they are
-            // drawn later in the scene. See
-            // https://docs.godotengine.org/en/latest/tutorials/3d/3d_rendering_limitations.html#transparency-sorting.
-            Instance {
-                position: (-6.0, -4.5, 0.0).into(),
-                scale: 0.5,
-                rotation: Rotor::identity(),
-                shader: Shader::SimpleTransparency,
-            },
-            Instance {
-                position: (3.0, -4.5, 3.0).into(),
-                scale: 0.75,
-                rotation: Rotor::identity(),
-                shader: Shader::Aerogel,
-            }
-        ];
-        instanced_entities.push(ModelWithInstances { id: 1, instances: simple_cube_instances });
-        instanced_entities.push(ModelWithInstances {
-            id: 2,
-            instances: vec![Instance {
-                position: (-3.0, -4.5, 3.0).into(),
-                scale: 0.5,
-                rotation: Rotor::identity(),
-                shader: Shader::Pulse,
-            }],
-        });
-        instanced_entities.push(ModelWithInstances {
-            id: 3,
-            instances: vec![Instance {
-                position: (-3.0, -4.5, 6.0).into(),
-                scale: 0.5,
-                rotation: Rotor::identity(),
-                shader: Shader::ColorTween,
-            }],
-        });
-        instanced_entities.push(ModelWithInstances {
-            id: 4,
-            instances: vec![Instance {
-                position: (-6.0, -4.5, -3.0).into(),
-                scale: 0.5,
-                rotation: Rotor::identity(),
-                shader: Shader::SimpleTransparency,
-            }],
-        });
 
-        const CAMERA_EYE_Y: f32 = 5.0;
-        player_physics.position = (0.0, CAMERA_EYE_Y - CAMERA_PHYSICS_OFFSET, 10.0).into();
-        GameState {
-            player: Player {
-                camera: Camera::new(
-                    // position the camera 1 unit up and 2 units back
-                    // +z is out of the screen
-                    (0.0, CAMERA_EYE_Y, 10.0).into(),
-                    // have it look at the origin
-                    (0.0, -1.0, -2.0).into(),
-                    // which way is "up"
-                    Vector3::unit_y(),
-                    aspect_ratio,
-                    45.0,
-                    0.1,
-                    100.0,
-                ),
-                physics: player_physics,
-            },
+        // Bodies name their instance by its index among the model's instances.
+        let bodies: Vec<RigidBody> = scene
+            .bodies
+            .iter()
+            .map(|body| {
+                let (model, instance) = &scene.instances[body.instance];
+                let index = scene.instances[..body.instance]
+                    .iter()
+                    .filter(|(handle, _)| handle == model)
+                    .count();
+                RigidBody::new(
+                    InstanceId { model: *model, index },
+                    instance,
+                    scene.models.collision(*model),
+                    body.mass,
+                    body.restitution,
+                    body.friction,
+                )
+            })
+            .collect();
+        let body_indices =
+            bodies.iter().enumerate().map(|(index, body)| (body.id, index)).collect();
+
+        let camera = Camera::new(
+            scene.player_spawn,
+            scene.camera.direction.normalize(),
+            // which way is "up"
+            Vector3::unit_y(),
+            aspect_ratio,
+            scene.camera.fovy,
+            scene.camera.znear,
+            scene.camera.zfar,
+        );
+        let mut state = GameState {
+            player: Character::new(
+                scene.player_spawn,
+                camera,
+                tuning.max_slope,
+                tuning.step_height,
+            ),
             tick: 0,
-            update_instant: Instant::now(),
-            // cube_instances: instances,
-            //simple_cube_instances,
+            tuning,
+            light: scene.light,
+            models: Arc::new(scene.models.clone()),
             instanced_entities,
-        }
+            broad_phase: BroadPhase::new(),
+            bodies,
+            body_indices: Arc::new(body_indices),
+            solver: Solver::new(),
+        };
+        state.fill_broad_phase();
+        // So that a player spawned on the ground can jump straight away.
+        let (player, world) = state.player_and_world();
+        player.land(&world);
+        state
     }
     pub fn change_camera_aspect(&mut self, aspect_ratio: f32) {
         self.player.camera.set_aspect(aspect_ratio);
@@ -192,74 +202,129 @@ impl GameState {
     pub fn get_camera(&self) -> Camera {
         self.player.camera
     }
-    pub fn update(&mut self, input: &InputState, step_time: Instant) {
+    pub fn player_position(&self) -> Point3<f32> {
+        self.player.physics.position
+    }
+    #[cfg(test)]
+    pub fn player_velocity(&self) -> Vector3<f32> {
+        self.player.physics.velocity
+    }
+    // The normal of the ground the player is standing on, or None in the air.
+    #[cfg(test)]
+    pub fn player_ground(&self) -> Option<Vector3<f32>> {
+        self.player.ground_normal()
+    }
+    // The number of updates so far.
+    pub fn tick(&self) -> isize {
+        self.tick
+    }
+    // Seconds of game time so far.
+    pub fn time(&self) -> f32 {
+        self.tick as f32 * self.tuning.time_per_tick().as_secs_f32()
+    }
+    pub fn get_light(&self) -> Light {
+        self.light
+    }
+    // A snapshot of what the renderer needs from this state. update_instant is when the last
+    // update was due, which the renderer interpolates from, and tick_duration is how much real
+    // time the next one will take.
+    pub fn snapshot(&self, update_instant: Instant, tick_duration: Duration) -> RenderSnapshot {
+        RenderSnapshot {
+            tick: self.tick,
+            update_instant,
+            tick_duration,
+            time: self.time(),
+            camera: self.player.camera,
+            entities: self.instanced_entities.clone(),
+        }
+    }
+    // Like snapshot(), but reuses the allocations already in `snapshot`.
+    pub fn write_snapshot(
+        &self,
+        snapshot: &mut RenderSnapshot,
+        update_instant: Instant,
+        tick_duration: Duration,
+    ) {
+        snapshot.tick = self.tick;
+        snapshot.update_instant = update_instant;
+        snapshot.tick_duration = tick_duration;
+        snapshot.time = self.time();
+        snapshot.camera = self.player.camera;
+        copy_entities(&mut snapshot.entities, &self.instanced_entities);
+    }
+    pub fn models(&self) -> &ModelRegistry {
+        &self.models
+    }
+    // The instances of a model, or nothing if the model has none.
+    pub fn instances(&self, model: ModelHandle) -> &[Instance] {
+        self.instanced_entities
+            .iter()
+            .find(|entity| entity.model == model)
+            .map_or(&[], |entity| &entity.instances)
+    }
+    // Advances the game by one tick, Tuning::time_per_tick long.
+    pub fn update(&mut self, input: &TickInput) {
         self.tick += 1;
-        self.update_instant = step_time;
-        self.player.physics.accel = (0.0, GRAVITY, 0.0).into();
-        let lateral_force = PLAYER_FORCE
+        if input.jump {
+            self.player.jump(JUMP_SPEED);
+        }
+        let player_force = self.tuning.player_force;
+        // The ground holds the player up.
+        let gravity = if self.player.is_grounded() { 0.0 } else { self.tuning.gravity };
+        self.player.physics.accel = (0.0, gravity, 0.0).into();
+        let lateral_force = player_force
             * cgmath::Vector3::normalize(
                 [-self.player.camera.direction.z, 0.0, self.player.camera.direction.x].into(),
             );
 
-        let delta_t = (*TIME_PER_GAME_TICK).as_secs_f32();
-        if input.right && !input.left {
-            self.player.physics.apply_force(lateral_force);
-        } else if input.left && !input.right {
-            self.player.physics.apply_force(-lateral_force);
-        } else {
-            // Neither or both are pressed, apply lateral damping.
-            self.player.physics.apply_force(
-                -Vector3::dot(
-                    self.player.physics.mass * self.player.physics.velocity,
-                    lateral_force / PLAYER_FORCE,
-                ) * (lateral_force / PLAYER_FORCE)
-                    * (1.0 / (10.0 * delta_t)),
-            );
-        }
-        let fwd_force = PLAYER_FORCE
+        let delta_t = self.tuning.time_per_tick().as_secs_f32();
+        // Analog input pushes with part of the force and damps with the rest, so a half-tilted
+        // stick settles at a lower speed. Neither or both directions pressed is full damping.
+        let strafe = input.strafe_right - input.strafe_left;
+        self.player.physics.apply_force(strafe * lateral_force);
+        self.player.physics.apply_force(
+            -Vector3::dot(
+                self.player.physics.mass * self.player.physics.velocity,
+                lateral_force / player_force,
+            ) * (lateral_force / player_force)
+                * ((1.0 - strafe.abs()) / (10.0 * delta_t)),
+        );
+        let fwd_force = player_force
             * cgmath::Vector3::normalize(
                 [self.player.camera.direction.x, 0.0, self.player.camera.direction.z].into(),
             );
 
         // TODO: this acts terribly if you quickly switch between left/right or fwd/back
-        if input.forward && !input.backward {
-            self.player.physics.apply_force(fwd_force);
-        } else if input.backward && !input.forward {
-            self.player.physics.apply_force(-fwd_force);
-        } else {
-            // Neither or both are pressed, apply forward damping.
-            self.player.physics.apply_force(
-                -Vector3::dot(
-                    self.player.physics.mass * self.player.physics.velocity,
-                    fwd_force / PLAYER_FORCE,
-                ) * (fwd_force / PLAYER_FORCE)
-                    * (1.0 / (10.0 * delta_t)),
-            );
-        }
-        if input.jump && self.player.physics.position.y <= -4.999 {
-            self.player.physics.velocity += (0.0, 5.0, 0.0).into();
-        }
-        let delta_pos = self.player.physics.update(delta_t, 10.0);
-        self.player.camera.eye += delta_pos;
-        if self.player.physics.position.y < -5.0 {
-            self.player.physics.position.y = -5.0;
-            self.player.physics.velocity.y = 0.0;
-            // TODO: clearly the player update code should be responsible for moving the eye /
-            // center-of-mass in tandem.
-            self.player.camera.eye =
-                self.player.physics.position + Vector3::new(0.0, CAMERA_PHYSICS_OFFSET, 0.0);
+        let forward = input.forward - input.backward;
+        self.player.physics.apply_force(forward * fwd_force);
+        self.player.physics.apply_force(
+            -Vector3::dot(
+                self.player.physics.mass * self.player.physics.velocity,
+                fwd_force / player_force,
+            ) * (fwd_force / player_force)
+                * ((1.0 - forward.abs()) / (10.0 * delta_t)),
+        );
+        let (player, world) = self.player_and_world();
+        let hits = player.step(delta_t, &world);
+        // Bodies get shoved by the player, as well as stopping it, in the middle of where the two
+        // meet.
+        for hit in hits {
+            if let Some(&body) = self.body_indices.get(&hit.key) {
+                let point = hit.bounds.intersection(&self.placed(hit.key).aabb()).center();
+                let mass = self.player.physics.mass;
+                self.bodies[body].push(point, hit.normal, hit.velocity, mass);
+            }
         }
 
-        const ROTATION_MOVEMENT_DEG: f32 = 0.1;
-        let lateral_rot = Rotor::from_axis_angle(
-            cgmath::Vector3::unit_y(),
-            cgmath::Deg(-ROTATION_MOVEMENT_DEG * input.mouse_x as f32),
-        );
+        self.step_bodies(delta_t);
+
+        let (look_x, look_y) = input.look;
+        let lateral_rot = Rotor::from_axis_angle(cgmath::Vector3::unit_y(), cgmath::Deg(-look_x));
         let vertical_rot = Rotor::from_axis_angle(
             cgmath::Vector3::normalize(
                 [self.player.camera.direction.z, 0.0, -self.player.camera.direction.x].into(),
             ),
-            cgmath::Deg(ROTATION_MOVEMENT_DEG * input.mouse_y as f32),
+            cgmath::Deg(look_y),
         );
         // Prevent the camera from getting too close to a vertical pole, while still allowing for
         // lateral movement.
@@ -276,34 +341,116 @@ impl GameState {
                 cgmath::Vector3::normalize(lateral_rot.rotate_vector(new_vertical));
         }
     }
+    // The player, and what it can run into.
+    fn player_and_world(&mut self) -> (&mut Character, Surroundings<'_>) {
+        let world = Surroundings {
+            models: &self.models,
+            entities: &self.instanced_entities,
+            broad_phase: &self.broad_phase,
+        };
+        (&mut self.player, world)
+    }
+    // Puts every instance of the scene in the broad phase.
+    fn fill_broad_phase(&mut self) {
+        for entity in &self.instanced_entities {
+            let collision = self.models.collision(entity.model);
+            for (index, instance) in entity.instances.iter().enumerate() {
+                let aabb = Placed { collision, instance }.aabb();
+                self.broad_phase.insert(InstanceId { model: entity.model, index }, aabb);
+            }
+        }
+    }
+    // An instance's collision shape, where the instance is.
+    fn placed(&self, id: InstanceId) -> Placed<'_> {
+        Placed {
+            collision: self.models.collision(id.model),
+            instance: &self.instances(id.model)[id.index],
+        }
+    }
+    // Moves the bodies on by one tick: gravity speeds them up, contacts stop them moving into
+    // anything, and then they move.
+    fn step_bodies(&mut self, delta_t: f32) {
+        let gravity = Vector3::new(0.0, self.tuning.gravity, 0.0);
+        for body in &mut self.bodies {
+            body.physics.accel = gravity;
+            body.physics.angular_accel = Vector3::zero();
+            let spin = body.physics.angular_velocity;
+            body.physics.apply_torque(-spin * rigid_body::ANGULAR_DRAG);
+            body.physics.integrate_velocities(delta_t, rigid_body::MAX_SPEED);
+        }
+        let touches = self.touches();
+        self.solver.solve(&mut self.bodies, &touches, delta_t);
+        for body in &mut self.bodies {
+            body.physics.integrate_positions(delta_t);
+            let id = body.id;
+            let entity = self.instanced_entities.iter_mut().find(|e| e.model == id.model);
+            let instance = entity.and_then(|entity| entity.instances.get_mut(id.index));
+            let Some(instance) = instance else {
+                continue;
+            };
+            body.place(instance);
+            let collision = self.models.collision(id.model);
+            self.broad_phase.update(id, Placed { collision, instance }.aabb());
+        }
+    }
+    // Everything each body is touching, with each pair of bodies once.
+    fn touches(&self) -> Vec<Touch> {
+        let body = |id| self.body_indices.get(&id).copied();
+        let mut touches = vec![];
+        for (first, second) in self.broad_phase.pairs() {
+            // Bodies are pushed out of what they touch; between two bodies, the later one is what
+            // the earlier one is pushed out of.
+            let (a, b, id) = match (body(first), body(second)) {
+                (None, None) => continue,
+                (Some(a), None) => (a, None, second),
+                (None, Some(a)) => (a, None, first),
+                (Some(a), Some(b)) if a < b => (a, Some(b), second),
+                (Some(b), Some(a)) => (a, Some(b), first),
+            };
+            let placed = self.placed(self.bodies[a].id);
+            if placed.collision.kind == ColliderKind::None {
+                continue;
+            }
+            let bounds = placed.aabb();
+            let other = self.placed(id);
+            if let Some(contact) = other.push_out(&placed, &bounds) {
+                let points = rigid_body::contact_points(&placed, &other, &contact);
+                touches.push(Touch { a, b, contact, points });
+            }
+        }
+        // The solver works through touches in order. Going body by body, with what can't move
+        // last, gives the static world the last word on each pass, so bodies on top of others don't
+        // push them into the floor.
+        touches.sort_by_key(|touch| (touch.a, touch.b.is_none(), touch.b));
+        touches
+    }
 }
 
-pub struct InputState {
-    pub mouse_x: i32,
-    pub mouse_y: i32,
-    pub forward: bool,
-    pub backward: bool,
-    pub left: bool,
-    pub right: bool,
-    pub jump: bool,
+// What the player runs into: every instance, the floor included.
+struct Surroundings<'a> {
+    models: &'a ModelRegistry,
+    entities: &'a [ModelWithInstances],
+    broad_phase: &'a BroadPhase<InstanceId>,
 }
-
-impl InputState {
-    pub fn new() -> Self {
-        InputState {
-            mouse_x: 0,
-            mouse_y: 0,
-            forward: false,
-            backward: false,
-            left: false,
-            right: false,
-            jump: false,
-        }
+impl Surroundings<'_> {
+    fn placed(&self, id: InstanceId) -> Option<Placed<'_>> {
+        let entity = self.entities.iter().find(|entity| entity.model == id.model)?;
+        let instance = entity.instances.get(id.index)?;
+        Some(Placed { collision: self.models.collision(id.model), instance })
+    }
+}
+impl World for Surroundings<'_> {
+    type Key = InstanceId;
+    fn overlap(&self, shape: &Hull, bounds: &Aabb) -> Option<(Self::Key, Contact)> {
+        self.broad_phase
+            .query(bounds)
+            .filter_map(|id| Some((id, self.placed(id)?.push_out(shape, bounds)?)))
+            .max_by(|(_, a), (_, b)| a.depth.total_cmp(&b.depth))
     }
-    pub fn post_update_reset(&mut self) {
-        self.mouse_x = 0;
-        self.mouse_y = 0;
-        self.jump = false;
+    fn overlaps(&self, shape: &Hull, bounds: &Aabb) -> bool {
+        self.broad_phase
+            .query(bounds)
+            .any(|id| self.placed(id).is_some_and(|placed| placed.overlaps(shape, bounds)))
     }
 }
 
@@ -318,6 +465,21 @@ pub enum Shader {
     SimpleTransparency = 5,
     Aerogel = 6,
 }
+impl Shader {
+    // Looks up a shader by its snake_case name, as used in scene files.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "texture" => Some(Shader::Texture),
+            "non_material" => Some(Shader::NonMaterial),
+            "pulse" => Some(Shader::Pulse),
+            "ripple" => Some(Shader::Ripple),
+            "color_tween" => Some(Shader::ColorTween),
+            "simple_transparency" => Some(Shader::SimpleTransparency),
+            "aerogel" => Some(Shader::Aerogel),
+            _ => None,
+        }
+    }
+}
 
 #[derive(Clone, Copy)]
 pub struct Instance {
@@ -327,7 +489,16 @@ pub struct Instance {
     pub shader: Shader,
 }
 impl Instance {
-    pub fn to_raw(&self) -> InstanceRaw {
+    // Blends the transform from self (t = 0) to other (t = 1). The shader is taken from other.
+    pub fn lerp(&self, other: &Instance, t: f32) -> Instance {
+        Instance {
+            position: self.position.lerp(other.position, t),
+            scale: self.scale + (other.scale - self.scale) * t,
+            rotation: self.rotation.slerp(&other.rotation, t),
+            shader: other.shader,
+        }
+    }
+    pub fn to_raw(self) -> InstanceRaw {
         InstanceRaw {
             pos: self.position.into(),
             scale: self.scale,
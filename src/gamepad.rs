@@ -0,0 +1,156 @@
+/* Gamepad: turns the state of a pad into the same Events as the keyboard and mouse. Pads are
+ * polled rather than delivering messages, so each source keeps the state it last reported and
+ * emits events only for what changed. The platform backends read real pads; VirtualGamepad is
+ * driven by code, for tests. */
+use crate::events::{Event, GamepadAxis, GamepadButton};
+
+use std::collections::HashSet;
+
+pub trait Gamepad: Send {
+    // Appends an event for everything that changed since the last poll.
+    fn poll(&mut self, events: &mut Vec<Event>);
+}
+
+// A snapshot of a pad. Sources fill one in from the hardware and diff it against the last one.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PadState {
+    pub connected: bool,
+    pub buttons: HashSet<GamepadButton>,
+    // Indexed by GamepadAxis.
+    pub axes: [f32; 6],
+}
+impl PadState {
+    pub fn axis(&self, axis: GamepadAxis) -> f32 {
+        self.axes[axis as usize]
+    }
+    pub fn set_axis(&mut self, axis: GamepadAxis, value: f32) {
+        self.axes[axis as usize] = value;
+    }
+    // Appends the events that take a reader from `self` to `new`.
+    pub fn diff(&self, new: &PadState, events: &mut Vec<Event>) {
+        if self.connected && !new.connected {
+            events.push(Event::GamepadDisconnected);
+            return;
+        }
+        for button in GamepadButton::ALL {
+            match (self.buttons.contains(&button), new.buttons.contains(&button)) {
+                (false, true) => events.push(Event::GamepadButtonPressed { button }),
+                (true, false) => events.push(Event::GamepadButtonReleased { button }),
+                _ => {}
+            }
+        }
+        for axis in GamepadAxis::ALL {
+            if self.axis(axis) != new.axis(axis) {
+                events.push(Event::GamepadAxisMoved { axis, value: new.axis(axis) });
+            }
+        }
+    }
+}
+
+// A pad with no hardware behind it. Changes show up as events on the next poll.
+#[cfg(test)]
+pub struct VirtualGamepad {
+    state: PadState,
+    reported: PadState,
+}
+#[cfg(test)]
+impl VirtualGamepad {
+    pub fn new() -> Self {
+        VirtualGamepad {
+            state: PadState { connected: true, ..PadState::default() },
+            reported: PadState::default(),
+        }
+    }
+    pub fn set_axis(&mut self, axis: GamepadAxis, value: f32) {
+        self.state.set_axis(axis, value.clamp(-1.0, 1.0));
+    }
+    pub fn press(&mut self, button: GamepadButton) {
+        self.state.buttons.insert(button);
+    }
+    pub fn release(&mut self, button: GamepadButton) {
+        self.state.buttons.remove(&button);
+    }
+}
+#[cfg(test)]
+impl Default for VirtualGamepad {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+#[cfg(test)]
+impl Gamepad for VirtualGamepad {
+    fn poll(&mut self, events: &mut Vec<Event>) {
+        self.reported.diff(&self.state, events);
+        self.reported.clone_from(&self.state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::Tuning,
+        input::{Bindings, InputState},
+        simulation::Simulation,
+    };
+
+    // How far the player gets in a second of holding the left stick forward to `tilt`.
+    fn distance_moved(tilt: f32) -> f32 {
+        let mut simulation = Simulation::from_scene_text(
+            "model cube builtin:cube
+             player spawn=0,-5,0
+             camera direction=0,0,-1",
+            Tuning::default(),
+        )
+        .unwrap();
+        let mut input_state = InputState::new(Bindings::default());
+        let mut pad = VirtualGamepad::new();
+        pad.set_axis(GamepadAxis::LeftY, tilt);
+        let mut events = vec![];
+        pad.poll(&mut events);
+        for event in &events {
+            input_state.apply(event);
+        }
+
+        let delta_t = Tuning::default().time_per_tick().as_secs_f32();
+        let start = simulation.player_position();
+        for _ in 0..100 {
+            simulation.step(&input_state.tick_input(delta_t));
+            input_state.post_update_reset();
+        }
+        start.z - simulation.player_position().z
+    }
+
+    #[test]
+    fn stick_tilt_scales_movement() {
+        let resting = distance_moved(0.0);
+        let drift = distance_moved(0.1);
+        let half = distance_moved(0.6);
+        let full = distance_moved(1.0);
+        assert!(resting.abs() < 1e-4, "moved {resting} without input");
+        assert!((drift - resting).abs() < 1e-4, "moved {drift} inside the dead zone");
+        assert!(0.0 < half && half < full, "half tilt went {half}, full tilt {full}");
+    }
+
+    #[test]
+    fn only_changes_are_reported() {
+        let mut pad = VirtualGamepad::new();
+        let mut events = vec![];
+        pad.press(GamepadButton::A);
+        pad.set_axis(GamepadAxis::RightTrigger, 0.5);
+        pad.poll(&mut events);
+        assert_eq!(
+            events,
+            [
+                Event::GamepadButtonPressed { button: GamepadButton::A },
+                Event::GamepadAxisMoved { axis: GamepadAxis::RightTrigger, value: 0.5 },
+            ]
+        );
+        events.clear();
+        pad.poll(&mut events);
+        assert!(events.is_empty());
+        pad.release(GamepadButton::A);
+        pad.poll(&mut events);
+        assert_eq!(events, [Event::GamepadButtonReleased { button: GamepadButton::A }]);
+    }
+}
@@ -0,0 +1,120 @@
+/* Golden-image tests for the shader variants. Each variant is drawn on a single cube with a
+ * fixed camera and a fixed shader time, rendered offscreen, and compared against a reference PNG
+ * in tests/golden/. On a mismatch the actual frame and a diff image are written to
+ * target/golden/.
+ *
+ * Run with CUBE_UPDATE_GOLDEN=1 to (re)write the references after an intentional shader change. */
+use crate::{
+    config::{GraphicsConfig, Tuning},
+    game_state::GameState,
+    gpu_state::WebGPUState,
+    scene::Scene,
+};
+
+use image::RgbaImage;
+use pollster::block_on;
+use std::path::{Path, PathBuf};
+
+const WIDTH: u32 = 128;
+const HEIGHT: u32 = 128;
+const TIME_SECS: f32 = 1.25;
+// How far apart two channels may be before a pixel counts as mismatched. Rasterizers disagree
+// slightly along edges and in the last bit of sRGB conversion.
+const CHANNEL_TOLERANCE: u8 = 8;
+const MAX_MISMATCHED_FRACTION: f32 = 0.005;
+
+// Named as in scene files.
+const VARIANTS: [&str; 7] =
+    ["texture", "non_material", "pulse", "ripple", "color_tween", "simple_transparency", "aerogel"];
+
+// The textured cube is the only model with a material, so every variant is drawn on it. The player
+// spawns so that the eye ends up at (0, 0, 5), looking down -z.
+fn scene(shader_name: &str) -> GameState {
+    let text = format!(
+        "model cube cube.obj
+         instance cube position=0,0,0 axis=1,1,0 angle=30 shader={shader_name}
+         player spawn=0,-0.4,5
+         camera direction=0,0,-1 fov=45 near=0.1 far=100
+         light position=2,2,2 color=1,1,1"
+    );
+    let scene = Scene::parse("golden", &text).unwrap();
+    GameState::from_scene(&scene, WIDTH as f32 / HEIGHT as f32, Tuning::default())
+}
+
+fn render(shader_name: &str) -> RgbaImage {
+    let graphics = GraphicsConfig::default();
+    let mut gpu_state =
+        block_on(WebGPUState::new_headless(WIDTH, HEIGHT, &graphics, scene(shader_name)))
+            .expect("Golden tests need a wgpu adapter, software or otherwise");
+    gpu_state.set_fixed_time(Some(TIME_SECS));
+    let frame = gpu_state.render_to_rgba().expect("Offscreen rendering failed");
+    RgbaImage::from_raw(WIDTH, HEIGHT, frame).unwrap()
+}
+
+fn golden_path(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("golden").join(format!("{name}.png"))
+}
+
+fn output_path(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("target").join("golden").join(format!("{name}.png"))
+}
+
+// Returns the number of mismatched pixels, along with an image that shows them in red over a
+// dimmed copy of the expected frame.
+fn diff(expected: &RgbaImage, actual: &RgbaImage) -> (usize, RgbaImage) {
+    let mut mismatched = 0;
+    let mut diff_image = RgbaImage::new(expected.width(), expected.height());
+    for ((e, a), d) in expected.pixels().zip(actual.pixels()).zip(diff_image.pixels_mut()) {
+        let matches = e.0.iter().zip(a.0.iter()).all(|(e, a)| e.abs_diff(*a) <= CHANNEL_TOLERANCE);
+        if matches {
+            d.0 = [e.0[0] / 3, e.0[1] / 3, e.0[2] / 3, 255];
+        } else {
+            mismatched += 1;
+            d.0 = [255, 0, 0, 255];
+        }
+    }
+    (mismatched, diff_image)
+}
+
+// Returns a description of the failure, if any.
+fn check(name: &str, actual: &RgbaImage) -> Option<String> {
+    let golden = golden_path(name);
+    if std::env::var_os("CUBE_UPDATE_GOLDEN").is_some() {
+        std::fs::create_dir_all(golden.parent().unwrap()).unwrap();
+        actual.save(&golden).unwrap();
+        return None;
+    }
+    let expected = match image::open(&golden) {
+        Ok(expected) => expected.to_rgba8(),
+        Err(e) => return Some(format!("{name}: could not open {}: {e}", golden.display())),
+    };
+    if expected.dimensions() != actual.dimensions() {
+        return Some(format!(
+            "{name}: expected a {:?} frame but rendered {:?}",
+            expected.dimensions(),
+            actual.dimensions()
+        ));
+    }
+    let (mismatched, diff_image) = diff(&expected, actual);
+    let allowed = (MAX_MISMATCHED_FRACTION * (WIDTH * HEIGHT) as f32) as usize;
+    if mismatched <= allowed {
+        return None;
+    }
+    let actual_path = output_path(&format!("{name}-actual"));
+    let diff_path = output_path(&format!("{name}-diff"));
+    std::fs::create_dir_all(actual_path.parent().unwrap()).unwrap();
+    actual.save(&actual_path).unwrap();
+    diff_image.save(&diff_path).unwrap();
+    Some(format!(
+        "{name}: {mismatched} pixels differ (at most {allowed} allowed), see {} and {}",
+        actual_path.display(),
+        diff_path.display()
+    ))
+}
+
+#[test]
+fn shader_variants_match_golden_images() {
+    let failures =
+        VARIANTS.iter().filter_map(|name| check(name, &render(name))).collect::<Vec<_>>();
+    assert!(failures.is_empty(), "Golden image mismatches:\n{}", failures.join("\n"));
+}
@@ -1,69 +1,102 @@
 /* WebGPUState: data and behavior needed to create and render using WebGPU. */
 use crate::{
     camera::{Camera, CameraUniform},
-    game_state::{GameState, Instance},
+    config::GraphicsConfig,
+    game_state::{GameState, Instance, RenderSnapshot},
     light::LightUniform,
     model::{self, DescribeVB, Material, Mesh, ModelVertex},
+    platform::{Point, Rect, Window},
+    registry::ModelHandle,
     texture,
     time::TimeUniform,
 };
 
 use std::{
-    ffi::c_void,
     mem::{self},
     result::Result,
 };
-use std::{ops::Range, time::Instant};
+use std::{collections::BTreeMap, ops::Range};
 use wgpu::util::DeviceExt;
-use windows::Win32::Foundation::POINT;
-use windows::Win32::{
-    Foundation::{HINSTANCE, HWND, RECT},
-    UI::WindowsAndMessaging::*,
-};
-
-struct MyWindowHandle {
-    win32handle: raw_window_handle::Win32WindowHandle,
-}
-impl MyWindowHandle {
-    fn new(window: HWND, hinstance: HINSTANCE) -> Self {
-        let mut h = raw_window_handle::Win32WindowHandle::empty();
-        h.hwnd = window.0 as *mut c_void;
-        h.hinstance = hinstance.0 as *mut c_void;
-        Self { win32handle: h }
-    }
-}
-
-unsafe impl raw_window_handle::HasRawWindowHandle for MyWindowHandle {
-    fn raw_window_handle(&self) -> raw_window_handle::RawWindowHandle {
-        raw_window_handle::RawWindowHandle::Win32(self.win32handle)
-    }
-}
-
-unsafe impl raw_window_handle::HasRawDisplayHandle for MyWindowHandle {
-    fn raw_display_handle(&self) -> raw_window_handle::RawDisplayHandle {
-        raw_window_handle::RawDisplayHandle::from(raw_window_handle::WindowsDisplayHandle::empty())
-    }
-}
 
 struct ModelData {
     model: model::Model,
+    // What the instance buffer currently holds.
     instances: Vec<InstanceRaw>,
     buffer: wgpu::Buffer,
+    // Number of instances the buffer has room for.
+    capacity: usize,
 }
 impl ModelData {
-    fn new(device: &wgpu::Device, model: model::Model, instances: &Vec<Instance>) -> Self {
-        let instances_raw = instances.iter().map(Instance::to_raw).collect::<Vec<_>>();
-        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Simple Cube Instance Buffer"),
-            contents: bytemuck::cast_slice(&instances_raw),
-            usage: wgpu::BufferUsages::VERTEX,
-        });
-        ModelData { model, instances: instances_raw, buffer }
+    fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        model: model::Model,
+        instances: &[Instance],
+    ) -> Self {
+        let capacity = instances.len().max(1);
+        let buffer = create_instance_buffer(device, capacity);
+        let mut model_data = ModelData { model, instances: vec![], buffer, capacity };
+        model_data.sync(device, queue, instances);
+        model_data
+    }
+    // Brings the instance buffer in line with the given instances. The buffer is reallocated only
+    // when it has to grow; otherwise only the runs of instances that changed are written.
+    fn sync(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, instances: &[Instance]) {
+        if instances.len() > self.capacity {
+            self.capacity = instances.len().next_power_of_two();
+            self.buffer = create_instance_buffer(device, self.capacity);
+            self.instances.clear();
+        }
+        self.instances.truncate(instances.len());
+        let mut dirty_start = None;
+        for (i, instance) in instances.iter().enumerate() {
+            let raw = instance.to_raw();
+            if self.instances.get(i) == Some(&raw) {
+                if let Some(start) = dirty_start.take() {
+                    self.write_instances(queue, start..i);
+                }
+                continue;
+            }
+            if i < self.instances.len() {
+                self.instances[i] = raw;
+            } else {
+                self.instances.push(raw);
+            }
+            dirty_start.get_or_insert(i);
+        }
+        if let Some(start) = dirty_start {
+            self.write_instances(queue, start..instances.len());
+        }
+    }
+    fn write_instances(&self, queue: &wgpu::Queue, range: Range<usize>) {
+        queue.write_buffer(
+            &self.buffer,
+            (range.start * mem::size_of::<InstanceRaw>()) as wgpu::BufferAddress,
+            bytemuck::cast_slice(&self.instances[range]),
+        );
     }
 }
 
+fn create_instance_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Instance Buffer"),
+        size: (capacity * mem::size_of::<InstanceRaw>()) as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+// Color format of the offscreen target. RGBA so frames can be handed out without swizzling.
+const OFFSCREEN_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+enum RenderTarget {
+    Surface(wgpu::Surface),
+    // Headless mode draws into a texture that is never presented.
+    Offscreen(wgpu::Texture),
+}
+
 pub struct WebGPUState {
-    surface: wgpu::Surface,
+    target: RenderTarget,
     device: wgpu::Device,
     queue: wgpu::Queue,
     config: wgpu::SurfaceConfiguration,
@@ -72,18 +105,20 @@ pub struct WebGPUState {
     depth_texture: texture::Texture,
     camera_group: BindGroupData<CameraUniform>,
     light_group: BindGroupData<LightUniform>,
-    start_time: Instant,
+    // Game time from the last snapshot, in seconds.
+    time: f32,
+    // When set, shaders see this time instead of the game's.
+    fixed_time: Option<f32>,
     time_group: BindGroupData<TimeUniform>,
-    models: Vec<ModelData>,
+    models: BTreeMap<ModelHandle, ModelData>,
 }
 impl WebGPUState {
-    pub async fn new(window: HWND, hinstance: HINSTANCE, game_state: GameState) -> Self {
+    pub async fn new(window: &Window, graphics: &GraphicsConfig, game_state: GameState) -> Self {
         let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
-            backends: wgpu::Backends::all(),
+            backends: graphics.backends,
             ..Default::default()
         });
-        let my_handle = MyWindowHandle::new(window, hinstance);
-        let surface = unsafe { instance.create_surface(&my_handle) }.unwrap();
+        let surface = unsafe { instance.create_surface(window) }.unwrap();
         let adapter = instance
             .request_adapter(&wgpu::RequestAdapterOptions {
                 power_preference: wgpu::PowerPreference::HighPerformance,
@@ -92,42 +127,91 @@ impl WebGPUState {
             })
             .await
             .unwrap();
-        let (device, queue) = adapter
-            .request_device(
-                &wgpu::DeviceDescriptor {
-                    features: wgpu::Features::empty(),
-                    label: None,
-                    limits: wgpu::Limits::default(),
-                },
-                /* trace_path= */ None,
-            )
-            .await
-            .unwrap();
+        let (device, queue) = request_device(&adapter).await.unwrap();
         let surface_caps = surface.get_capabilities(&adapter);
         let surface_format = surface_caps
             .formats
             .iter()
             .copied()
-            .filter(|f| f.is_srgb())
-            .next()
+            .find(|f| f.is_srgb())
             .unwrap_or(surface_caps.formats[0]);
-        let mut rect: RECT = unsafe { mem::zeroed() };
-        unsafe {
-            let _ = GetClientRect(window, &mut rect);
+        // The automatic modes are always available; the others depend on the platform and driver.
+        let present_mode = match graphics.present_mode {
+            wgpu::PresentMode::AutoVsync | wgpu::PresentMode::AutoNoVsync => graphics.present_mode,
+            mode if surface_caps.present_modes.contains(&mode) => mode,
+            mode => {
+                println!("Present mode {mode:?} is not supported here, using vsync instead");
+                wgpu::PresentMode::AutoVsync
+            }
         };
-        let width = (rect.right - rect.left) as u32;
-        let height = (rect.bottom - rect.top) as u32;
+        let rect = window.client_rect();
+        let width = rect.width() as u32;
+        let height = rect.height() as u32;
         let config = wgpu::SurfaceConfiguration {
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            // COPY_SRC lets frames be read back for screenshots, where the surface supports it.
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | (surface_caps.usages & wgpu::TextureUsages::COPY_SRC),
             format: surface_format,
             width,
             height,
-            present_mode: surface_caps.present_modes[0],
+            present_mode,
             alpha_mode: surface_caps.alpha_modes[0], // TODO: how to get Pre/Postmultiplied in here?
             view_formats: vec![],
         };
         surface.configure(&device, &config);
 
+        Self::from_device(device, queue, RenderTarget::Surface(surface), config, game_state).await
+    }
+    // Creates a state that renders into an offscreen texture instead of a window. If no hardware
+    // adapter is available, a software fallback adapter is used, so this works on machines without
+    // a GPU.
+    pub async fn new_headless(
+        width: u32,
+        height: u32,
+        graphics: &GraphicsConfig,
+        game_state: GameState,
+    ) -> anyhow::Result<Self> {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+            backends: graphics.backends,
+            ..Default::default()
+        });
+        let mut adapter = None;
+        for force_fallback_adapter in [false, true] {
+            adapter = instance
+                .request_adapter(&wgpu::RequestAdapterOptions {
+                    power_preference: wgpu::PowerPreference::HighPerformance,
+                    force_fallback_adapter,
+                    compatible_surface: None,
+                })
+                .await;
+            if adapter.is_some() {
+                break;
+            }
+        }
+        let adapter = adapter.ok_or_else(|| anyhow::anyhow!("No wgpu adapter available"))?;
+        let (device, queue) = request_device(&adapter).await?;
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            format: OFFSCREEN_FORMAT,
+            width,
+            height,
+            // Never presented, so these two are unused.
+            present_mode: wgpu::PresentMode::Fifo,
+            alpha_mode: wgpu::CompositeAlphaMode::Opaque,
+            view_formats: vec![],
+        };
+        let texture = create_offscreen_texture(&device, &config);
+
+        Ok(Self::from_device(device, queue, RenderTarget::Offscreen(texture), config, game_state)
+            .await)
+    }
+    async fn from_device(
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        target: RenderTarget,
+        config: wgpu::SurfaceConfiguration,
+        game_state: GameState,
+    ) -> Self {
         let texture_bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
                 entries: &[
@@ -161,7 +245,6 @@ impl WebGPUState {
             wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
             wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
         );
-        let start_time = Instant::now();
         let time_group = BindGroupData::<TimeUniform>::new(
             TimeUniform::new(0.0),
             &device,
@@ -170,12 +253,7 @@ impl WebGPUState {
             wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
         );
         let light_group = BindGroupData::<LightUniform>::new(
-            LightUniform {
-                position: [2.0, 2.0, 2.0],
-                _padding: 0,
-                color: [1.0, 1.0, 1.0],
-                _padding2: 0,
-            },
+            LightUniform::from_light(&game_state.get_light()),
             &device,
             "Light",
             wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
@@ -210,43 +288,18 @@ impl WebGPUState {
             )
         };
 
-        let models = vec![
-            ModelData::new(
-                &device,
-                model::load_model("cube.obj", &device, &queue, &texture_bind_group_layout)
-                    .await
-                    .unwrap(),
-                &game_state.instanced_entities[0].instances,
-            ),
-            // simple cube
-            ModelData::new(
-                &device,
-                model::cube_model(&device),
-                &game_state.instanced_entities[1].instances,
-            ),
-            ModelData::new(
-                &device,
-                model::load_model("sphere-flat.obj", &device, &queue, &texture_bind_group_layout)
-                    .await
-                    .unwrap(),
-                &game_state.instanced_entities[2].instances,
-            ),
-            ModelData::new(
-                &device,
-                model::load_model("sphere.obj", &device, &queue, &texture_bind_group_layout)
-                    .await
-                    .unwrap(),
-                &game_state.instanced_entities[3].instances,
-            ),
-            ModelData::new(
-                &device,
-                model::double_cube_model(&device),
-                &game_state.instanced_entities[4].instances,
-            ),
-        ];
+        let mut models = BTreeMap::new();
+        for handle in game_state.models().handles() {
+            let geometry = game_state.models().geometry(handle);
+            let model = model::load_model(geometry, &device, &queue, &texture_bind_group_layout)
+                .await
+                .unwrap();
+            let instances = game_state.instances(handle);
+            models.insert(handle, ModelData::new(&device, &queue, model, instances));
+        }
 
         Self {
-            surface,
+            target,
             device,
             queue,
             config,
@@ -255,27 +308,41 @@ impl WebGPUState {
             depth_texture,
             camera_group,
             light_group,
-            start_time,
+            time: game_state.time(),
+            fixed_time: None,
             time_group,
             models,
         }
     }
-    pub fn resize(&mut self, rect: RECT) {
-        let w = (rect.right - rect.left) as u32;
-        let h = (rect.bottom - rect.top) as u32;
-        if w > 0 && h > 0 {
-            self.config.width = (rect.right - rect.left) as u32;
-            self.config.height = (rect.bottom - rect.top) as u32;
-            self.surface.configure(&self.device, &self.config);
+    // Minimized windows report a size of zero, which a surface can't have, so those keep the last
+    // size until the window comes back.
+    pub fn resize(&mut self, rect: Rect) {
+        let w = rect.width() as u32;
+        let h = rect.height() as u32;
+        if w == 0 || h == 0 || (w, h) == self.size() {
+            return;
+        }
+        self.config.width = w;
+        self.config.height = h;
+        self.configure_target();
+    }
+    // Recreates the render target and depth texture at the size in self.config.
+    fn configure_target(&mut self) {
+        match &mut self.target {
+            RenderTarget::Surface(surface) => surface.configure(&self.device, &self.config),
+            RenderTarget::Offscreen(texture) => {
+                *texture = create_offscreen_texture(&self.device, &self.config)
+            }
         }
         self.depth_texture =
             texture::create_depth_texture(&self.device, &self.config, "depth_texture");
     }
-    pub fn update_bg_color(&mut self, point: &POINT) {
+    pub fn update_bg_color(&mut self, point: &Point) {
+        let (width, height) = (self.config.width as f64, self.config.height as f64);
         self.background_color = wgpu::Color {
-            r: (point.x as f64) / 2560.0,
-            g: (point.y as f64) / 1440.0,
-            b: 0.5 + 0.25 * (point.x * point.y) as f64 / (2560.0 * 1440.0),
+            r: point.x as f64 / width,
+            g: point.y as f64 / height,
+            b: 0.5 + 0.25 * (point.x * point.y) as f64 / (width * height),
             a: 1.0,
         };
         // Not necessary anymore: new model is we repeatedly call render in a loop.
@@ -291,9 +358,57 @@ impl WebGPUState {
         // Not necessary anymore: new model is we repeatedly call render in a loop.
         // let _ = self.render();
     }
+    // Uploads any instances that were added, removed or moved since the last call.
+    pub fn update_instances(&mut self, snapshot: &RenderSnapshot) {
+        for (handle, model_data) in &mut self.models {
+            model_data.sync(&self.device, &self.queue, snapshot.instances(*handle));
+        }
+    }
+    // Shaders animate by game time, so they stop when the game is paused.
+    pub fn update_time(&mut self, time_secs: f32) {
+        self.time = time_secs;
+    }
+    pub fn set_fixed_time(&mut self, time_secs: Option<f32>) {
+        self.fixed_time = time_secs;
+    }
     pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
-        let output = self.surface.get_current_texture()?;
-        let view = output.texture.create_view(&wgpu::TextureViewDescriptor::default());
+        match self.render_frame(false) {
+            // Switching window modes can invalidate the surface before the Resized event arrives.
+            Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+                self.configure_target();
+                Ok(())
+            }
+            // The frame is dropped, and the next one tries again.
+            Err(wgpu::SurfaceError::Timeout) => Ok(()),
+            // Only running out of memory is left, which nothing can recover from.
+            result => result.map(|_| ()),
+        }
+    }
+    // Renders a frame and copies it back to the CPU as tightly packed RGBA8 rows.
+    pub fn render_to_rgba(&mut self) -> anyhow::Result<Vec<u8>> {
+        if !self.config.usage.contains(wgpu::TextureUsages::COPY_SRC) {
+            anyhow::bail!("This surface does not support reading frames back");
+        }
+        Ok(self.render_frame(true)?.unwrap_or_default())
+    }
+    // Waits for all submitted work to finish on the GPU.
+    pub fn finish(&self) {
+        self.device.poll(wgpu::Maintain::Wait);
+    }
+    pub fn size(&self) -> (u32, u32) {
+        (self.config.width, self.config.height)
+    }
+    fn render_frame(&mut self, read_back: bool) -> Result<Option<Vec<u8>>, wgpu::SurfaceError> {
+        let output = match &self.target {
+            RenderTarget::Surface(surface) => Some(surface.get_current_texture()?),
+            RenderTarget::Offscreen(_) => None,
+        };
+        let texture = match (&output, &self.target) {
+            (Some(output), _) => &output.texture,
+            (None, RenderTarget::Offscreen(texture)) => texture,
+            (None, RenderTarget::Surface(_)) => unreachable!(),
+        };
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
         let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
             label: Some("Render Encoder"),
         });
@@ -324,29 +439,31 @@ impl WebGPUState {
             render_pass.set_bind_group(1, &self.camera_group.bind_group, &[]);
             render_pass.set_bind_group(2, &self.light_group.bind_group, &[]);
             render_pass.set_bind_group(3, &self.time_group.bind_group, &[]);
-            let time = (Instant::now() - self.start_time).as_secs_f32();
+            let time = self.fixed_time.unwrap_or(self.time);
             self.queue.write_buffer(&self.time_group.buffer, 0, bytemuck::cast_slice(&[time]));
 
-            for model_data in &self.models {
+            for model_data in self.models.values().filter(|m| !m.instances.is_empty()) {
                 render_pass.set_vertex_buffer(1, model_data.buffer.slice(..));
                 for mesh in &model_data.model.meshes {
                     draw_mesh_instanced(
                         &mut render_pass,
                         mesh,
-                        if mesh.material.is_some() {
-                            Some(&model_data.model.materials[mesh.material.unwrap()])
-                        } else {
-                            None
-                        },
+                        mesh.material.map(|m| &model_data.model.materials[m]),
                         0..model_data.instances.len() as u32,
                     );
                 }
             }
         }
 
+        let readback =
+            read_back.then(|| copy_to_readback(&self.device, &mut encoder, texture, &self.config));
+
         // submit will accept anything that implements IntoIter
         self.queue.submit(std::iter::once(encoder.finish()));
-        output.present();
+        let frame = readback.map(|readback| readback.read(&self.device, &self.config));
+        if let Some(output) = output {
+            output.present();
+        }
 
         // BAD CODE ALERT: update the light's position each frame. I need to move this into the game
         // state. I'm just lazy right now.
@@ -361,10 +478,112 @@ impl WebGPUState {
         //     bytemuck::cast_slice(&[self.light_group.uniform]),
         // );
 
-        Ok(())
+        Ok(frame)
     }
 }
 
+async fn request_device(
+    adapter: &wgpu::Adapter,
+) -> Result<(wgpu::Device, wgpu::Queue), wgpu::RequestDeviceError> {
+    adapter
+        .request_device(
+            &wgpu::DeviceDescriptor {
+                features: wgpu::Features::empty(),
+                label: None,
+                limits: wgpu::Limits::default(),
+            },
+            /* trace_path= */ None,
+        )
+        .await
+}
+
+fn create_offscreen_texture(
+    device: &wgpu::Device,
+    config: &wgpu::SurfaceConfiguration,
+) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Offscreen Texture"),
+        size: wgpu::Extent3d {
+            width: config.width,
+            height: config.height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: config.format,
+        usage: config.usage,
+        view_formats: &[],
+    })
+}
+
+// A frame that has been copied into a mappable buffer but not yet read.
+struct Readback {
+    buffer: wgpu::Buffer,
+    padded_bytes_per_row: u32,
+}
+impl Readback {
+    // Waits for the copy to land and unpacks the buffer into RGBA8 rows without padding.
+    fn read(self, device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) -> Vec<u8> {
+        let slice = self.buffer.slice(..);
+        slice.map_async(wgpu::MapMode::Read, |_| ());
+        device.poll(wgpu::Maintain::Wait);
+        let padded = slice.get_mapped_range();
+        let bytes_per_row = (4 * config.width) as usize;
+        let mut rgba = Vec::with_capacity(bytes_per_row * config.height as usize);
+        for row in padded.chunks(self.padded_bytes_per_row as usize) {
+            rgba.extend_from_slice(&row[..bytes_per_row]);
+        }
+        drop(padded);
+        self.buffer.unmap();
+        if matches!(
+            config.format,
+            wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb
+        ) {
+            for pixel in rgba.chunks_exact_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+        rgba
+    }
+}
+
+fn copy_to_readback(
+    device: &wgpu::Device,
+    encoder: &mut wgpu::CommandEncoder,
+    texture: &wgpu::Texture,
+    config: &wgpu::SurfaceConfiguration,
+) -> Readback {
+    // Rows of a texture-to-buffer copy have to be aligned to COPY_BYTES_PER_ROW_ALIGNMENT.
+    let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+    let padded_bytes_per_row = (4 * config.width).div_ceil(align) * align;
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Readback Buffer"),
+        size: (padded_bytes_per_row * config.height) as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+    encoder.copy_texture_to_buffer(
+        wgpu::ImageCopyTexture {
+            aspect: wgpu::TextureAspect::All,
+            texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+        },
+        wgpu::ImageCopyBuffer {
+            buffer: &buffer,
+            layout: wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(padded_bytes_per_row),
+                rows_per_image: Some(config.height),
+            },
+        },
+        wgpu::Extent3d { width: config.width, height: config.height, depth_or_array_layers: 1 },
+    );
+    Readback { buffer, padded_bytes_per_row }
+}
+
+#[allow(clippy::too_many_arguments)]
 fn create_render_pipeline(
     label: &str,
     device: &wgpu::Device,
@@ -441,15 +660,15 @@ fn draw_mesh_instanced<'a>(
 ) {
     render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
     render_pass.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
-    if material.is_some() {
-        render_pass.set_bind_group(0, &material.unwrap().bind_group, &[]);
+    if let Some(material) = material {
+        render_pass.set_bind_group(0, &material.bind_group, &[]);
     }
     render_pass.draw_indexed(0..mesh.num_elements, 0, instances);
 }
 
 // Data for the graphics pipeline.
 #[repr(C)]
-#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+#[derive(Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct InstanceRaw {
     pub pos: [f32; 3],
     pub scale: f32,
@@ -507,11 +726,10 @@ impl<T: bytemuck::Pod> BindGroupData<T> {
         usage: wgpu::BufferUsages,
         visibility: wgpu::ShaderStages,
     ) -> BindGroupData<T> {
-        let uniform = uniform;
         let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some(&format!("{} Buffer", label)),
             contents: bytemuck::cast_slice(&[uniform]),
-            usage: usage,
+            usage,
         });
         let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             entries: &[wgpu::BindGroupLayoutEntry {
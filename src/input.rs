@@ -0,0 +1,629 @@
+/* Input: maps keys, mouse buttons and mouse motion onto the actions the game understands. The
+ * bindings come from a file in assets/, one action per line:
+ *
+ *   <action> = <input>, <input>, ...
+ *
+ * Actions are move_forward, move_backward, strafe_left, strafe_right, jump and look. Inputs are
+ * key names (W, Space, Left, F5, ...), mouse_left, mouse_right or mouse_middle, gamepad buttons
+ * (pad_a, pad_lb, pad_start, pad_up, ...), stick directions (left_stick_up, right_stick_left,
+ * ...) and left_trigger or right_trigger. look only takes mouse, left_stick or right_stick. An
+ * action that isn't listed keeps its default bindings; one listed with nothing after the '=' is
+ * unbound.
+ *
+ * The same file sets how analog input is read, with `<setting> = <number>` lines:
+ *
+ *   dead_zone          fraction of stick or trigger travel that is ignored
+ *   response_curve     exponent applied past the dead zone; above 1 is finer near rest
+ *   mouse_sensitivity  degrees turned per pixel of mouse motion
+ *   stick_look_speed   degrees turned per second with the look stick all the way over
+ *
+ * '#' starts a comment. F9 reloads the file while the game runs.
+ */
+use crate::{
+    events::{Event, GamepadAxis, GamepadButton, Key, MouseButton},
+    resources::{self, ParseError},
+};
+
+use std::collections::{HashMap, HashSet};
+
+pub const RELOAD_BINDINGS_KEY: Key = Key::F(9);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    MoveForward,
+    MoveBackward,
+    StrafeLeft,
+    StrafeRight,
+    Jump,
+    Look,
+}
+impl Action {
+    const ALL: [Action; 6] = [
+        Action::MoveForward,
+        Action::MoveBackward,
+        Action::StrafeLeft,
+        Action::StrafeRight,
+        Action::Jump,
+        Action::Look,
+    ];
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::MoveForward => "move_forward",
+            Action::MoveBackward => "move_backward",
+            Action::StrafeLeft => "strafe_left",
+            Action::StrafeRight => "strafe_right",
+            Action::Jump => "jump",
+            Action::Look => "look",
+        }
+    }
+    pub fn from_name(name: &str) -> Option<Action> {
+        Action::ALL.into_iter().find(|action| action.name() == name)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Stick {
+    Left,
+    Right,
+}
+impl Stick {
+    fn axes(self) -> (GamepadAxis, GamepadAxis) {
+        match self {
+            Stick::Left => (GamepadAxis::LeftX, GamepadAxis::LeftY),
+            Stick::Right => (GamepadAxis::RightX, GamepadAxis::RightY),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Input {
+    Key(Key),
+    MouseButton(MouseButton),
+    GamepadButton(GamepadButton),
+    // One direction of an axis: the positive or negative half of a stick axis, or a trigger.
+    GamepadAxis { axis: GamepadAxis, positive: bool },
+    // Only meaningful for Look.
+    MouseMotion,
+    GamepadStick(Stick),
+}
+impl Input {
+    pub fn from_name(name: &str) -> Option<Input> {
+        let half = |axis, positive| Some(Input::GamepadAxis { axis, positive });
+        match name.to_ascii_lowercase().as_str() {
+            "mouse" => Some(Input::MouseMotion),
+            "mouse_left" => Some(Input::MouseButton(MouseButton::Left)),
+            "mouse_right" => Some(Input::MouseButton(MouseButton::Right)),
+            "mouse_middle" => Some(Input::MouseButton(MouseButton::Middle)),
+            "left_stick" => Some(Input::GamepadStick(Stick::Left)),
+            "right_stick" => Some(Input::GamepadStick(Stick::Right)),
+            "left_stick_up" => half(GamepadAxis::LeftY, true),
+            "left_stick_down" => half(GamepadAxis::LeftY, false),
+            "left_stick_left" => half(GamepadAxis::LeftX, false),
+            "left_stick_right" => half(GamepadAxis::LeftX, true),
+            "right_stick_up" => half(GamepadAxis::RightY, true),
+            "right_stick_down" => half(GamepadAxis::RightY, false),
+            "right_stick_left" => half(GamepadAxis::RightX, false),
+            "right_stick_right" => half(GamepadAxis::RightX, true),
+            "left_trigger" => half(GamepadAxis::LeftTrigger, true),
+            "right_trigger" => half(GamepadAxis::RightTrigger, true),
+            lower => match GamepadButton::ALL.into_iter().find(|button| button.name() == lower) {
+                Some(button) => Some(Input::GamepadButton(button)),
+                None => Key::from_name(name).map(Input::Key),
+            },
+        }
+    }
+    fn is_look(self) -> bool {
+        matches!(self, Input::MouseMotion | Input::GamepadStick(_))
+    }
+}
+
+// How raw analog values turn into action values and camera motion.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AnalogSettings {
+    pub dead_zone: f32,
+    pub response_curve: f32,
+    pub mouse_sensitivity: f32,
+    pub stick_look_speed: f32,
+}
+impl Default for AnalogSettings {
+    fn default() -> Self {
+        AnalogSettings {
+            dead_zone: 0.2,
+            response_curve: 1.5,
+            mouse_sensitivity: 0.1,
+            stick_look_speed: 180.0,
+        }
+    }
+}
+impl AnalogSettings {
+    // Maps how far a stick or trigger is pushed, from 0 to 1, to how strongly it acts. Nothing
+    // happens inside the dead zone, and the rest of the travel is stretched back out to 0..1.
+    pub fn shape(&self, magnitude: f32) -> f32 {
+        if magnitude <= self.dead_zone {
+            return 0.0;
+        }
+        ((magnitude.min(1.0) - self.dead_zone) / (1.0 - self.dead_zone)).powf(self.response_curve)
+    }
+    // Sets a value by its name in bindings files. Returns None if there's no such setting.
+    fn set(&mut self, name: &str, value: f32) -> Option<Result<(), String>> {
+        let (field, valid) = match name {
+            "dead_zone" => (&mut self.dead_zone, (0.0..1.0).contains(&value)),
+            "response_curve" => (&mut self.response_curve, value > 0.0),
+            "mouse_sensitivity" => (&mut self.mouse_sensitivity, value.is_finite()),
+            "stick_look_speed" => (&mut self.stick_look_speed, value.is_finite()),
+            _ => return None,
+        };
+        if !valid {
+            return Some(Err(format!("{value} is out of range for {name}")));
+        }
+        *field = value;
+        Some(Ok(()))
+    }
+}
+
+#[derive(Clone)]
+pub struct Bindings {
+    inputs: HashMap<Action, Vec<Input>>,
+    pub analog: AnalogSettings,
+}
+impl Default for Bindings {
+    // WASD and the arrow keys to move, space to jump, and the mouse to look around. On a pad, the
+    // left stick moves, the right stick looks and A jumps.
+    fn default() -> Self {
+        let mut bindings = Bindings { inputs: HashMap::new(), analog: AnalogSettings::default() };
+        let key = |c| Input::Key(Key::Letter(c));
+        let half = |axis, positive| Input::GamepadAxis { axis, positive };
+        bindings.inputs.insert(
+            Action::MoveForward,
+            vec![key('W'), Input::Key(Key::Up), half(GamepadAxis::LeftY, true)],
+        );
+        bindings.inputs.insert(
+            Action::MoveBackward,
+            vec![key('S'), Input::Key(Key::Down), half(GamepadAxis::LeftY, false)],
+        );
+        bindings.inputs.insert(
+            Action::StrafeLeft,
+            vec![key('A'), Input::Key(Key::Left), half(GamepadAxis::LeftX, false)],
+        );
+        bindings.inputs.insert(
+            Action::StrafeRight,
+            vec![key('D'), Input::Key(Key::Right), half(GamepadAxis::LeftX, true)],
+        );
+        bindings.inputs.insert(
+            Action::Jump,
+            vec![Input::Key(Key::Space), Input::GamepadButton(GamepadButton::A)],
+        );
+        bindings
+            .inputs
+            .insert(Action::Look, vec![Input::MouseMotion, Input::GamepadStick(Stick::Right)]);
+        bindings
+    }
+}
+impl Bindings {
+    // Loads a bindings file from the assets directory.
+    pub async fn load(file_name: &str) -> anyhow::Result<Self> {
+        let text = resources::load_string(file_name).await?;
+        Ok(Self::parse(file_name, &text)?)
+    }
+    // Parses bindings text on top of the defaults. file_name is only used for error messages.
+    pub fn parse(file_name: &str, text: &str) -> Result<Self, ParseError> {
+        let mut bindings = Bindings::default();
+        let mut seen = HashSet::new();
+        let mut seen_settings = HashSet::new();
+        for (index, line) in text.lines().enumerate() {
+            let error = |message: String| ParseError {
+                file_name: file_name.to_string(),
+                line: index + 1,
+                message,
+            };
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let Some((name, inputs)) = line.split_once('=') else {
+                return Err(error("Expected '<action> = <input>, ...'".to_string()));
+            };
+            let name = name.trim();
+            let inputs = inputs.trim();
+            let Some(action) = Action::from_name(name) else {
+                let value =
+                    inputs.parse::<f32>().map_err(|_| error(format!("Unknown action '{name}'")))?;
+                if !seen_settings.insert(name) {
+                    return Err(error(format!("{name} is set more than once")));
+                }
+                match bindings.analog.set(name, value) {
+                    Some(result) => result.map_err(error)?,
+                    None => return Err(error(format!("Unknown setting '{name}'"))),
+                }
+                continue;
+            };
+            if !seen.insert(action) {
+                return Err(error(format!("{} is bound more than once", action.name())));
+            }
+            bindings.inputs.insert(action, vec![]);
+            for name in inputs.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+                let input = Input::from_name(name)
+                    .ok_or_else(|| error(format!("Unknown input '{name}'")))?;
+                bindings.bind(action, input).map_err(error)?;
+            }
+        }
+        Ok(bindings)
+    }
+    pub fn inputs(&self, action: Action) -> &[Input] {
+        self.inputs.get(&action).map_or(&[], |inputs| inputs)
+    }
+    // Adds an input to an action, alongside whatever is already bound to it.
+    pub fn bind(&mut self, action: Action, input: Input) -> Result<(), String> {
+        self.check(action, input)?;
+        let inputs = self.inputs.entry(action).or_default();
+        if !inputs.contains(&input) {
+            inputs.push(input);
+        }
+        Ok(())
+    }
+    fn check(&self, action: Action, input: Input) -> Result<(), String> {
+        match (action == Action::Look, input.is_look()) {
+            (true, false) => Err("look can only be bound to mouse or a stick".to_string()),
+            (false, true) => {
+                Err(format!("{} needs a key, button or stick direction", action.name()))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+// The controls for one tick, as GameState::update sees them. Unlike InputState this doesn't
+// depend on the bindings, which makes it what input recordings store.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TickInput {
+    // How strongly each direction is held, from 0 to 1.
+    pub forward: f32,
+    pub backward: f32,
+    pub strafe_left: f32,
+    pub strafe_right: f32,
+    // Set on the tick jump is pressed.
+    pub jump: bool,
+    // Degrees to turn, x to the right and y down.
+    pub look: (f32, f32),
+}
+
+// What the player is doing with the controls between two game ticks. Besides what is held right
+// now, it keeps what was pressed since the last tick, so that a tap shorter than a tick still
+// registers, and which actions were held at the last tick, so that actions like jump
+// fire once per press rather than on every tick or OS auto-repeat.
+pub struct InputState {
+    bindings: Bindings,
+    // Keys and buttons that are down.
+    held: HashSet<Input>,
+    // Keys and buttons that went down since the last tick. Auto-repeats don't count.
+    pressed: HashSet<Input>,
+    // Actions that were held as of the last tick.
+    held_last_tick: HashSet<Action>,
+    // Raw gamepad axes, indexed by GamepadAxis.
+    axes: [f32; 6],
+    // Mouse motion since the last tick.
+    look_x: f32,
+    look_y: f32,
+}
+
+impl InputState {
+    pub fn new(bindings: Bindings) -> Self {
+        InputState {
+            bindings,
+            held: HashSet::new(),
+            pressed: HashSet::new(),
+            held_last_tick: HashSet::new(),
+            axes: [0.0; 6],
+            look_x: 0.0,
+            look_y: 0.0,
+        }
+    }
+    // For rebinding at runtime. Takes effect from the next update.
+    pub fn bindings_mut(&mut self) -> &mut Bindings {
+        &mut self.bindings
+    }
+    // Whether any input bound to the action is held down. Stick directions and triggers count
+    // once they're more than halfway.
+    pub fn is_held(&self, action: Action) -> bool {
+        self.value(action) > 0.5
+    }
+    // How strongly the action is held, from 0 to 1: 1 for a held key or button, or how far a bound
+    // stick direction or trigger is pushed past the dead zone. The strongest input wins.
+    pub fn value(&self, action: Action) -> f32 {
+        let value = |input: &Input| match *input {
+            Input::GamepadAxis { axis, positive } => self.axis_value(axis, positive),
+            input if self.held.contains(&input) => 1.0,
+            _ => 0.0,
+        };
+        self.bindings.inputs(action).iter().map(value).fold(0.0, f32::max)
+    }
+    fn axis_value(&self, axis: GamepadAxis, positive: bool) -> f32 {
+        let (x, y) = match axis {
+            GamepadAxis::LeftX | GamepadAxis::LeftY => self.stick(Stick::Left),
+            GamepadAxis::RightX | GamepadAxis::RightY => self.stick(Stick::Right),
+            GamepadAxis::LeftTrigger | GamepadAxis::RightTrigger => {
+                return self.bindings.analog.shape(self.axes[axis as usize]);
+            }
+        };
+        let value = match axis {
+            GamepadAxis::LeftX | GamepadAxis::RightX => x,
+            _ => y,
+        };
+        if positive {
+            value.max(0.0)
+        } else {
+            (-value).max(0.0)
+        }
+    }
+    // The stick position after the dead zone and response curve. These apply to how far the stick
+    // is pushed in any direction rather than per axis, so diagonals aren't distorted.
+    fn stick(&self, stick: Stick) -> (f32, f32) {
+        let (x_axis, y_axis) = stick.axes();
+        let (x, y) = (self.axes[x_axis as usize], self.axes[y_axis as usize]);
+        let magnitude = (x * x + y * y).sqrt();
+        if magnitude == 0.0 {
+            return (0.0, 0.0);
+        }
+        let scale = self.bindings.analog.shape(magnitude) / magnitude;
+        (x * scale, y * scale)
+    }
+    // Whether the action started being held since the last tick. True for one tick per press, even
+    // if the input was let go again before the tick.
+    pub fn just_pressed(&self, action: Action) -> bool {
+        !self.held_last_tick.contains(&action)
+            && (self.is_held(action) || self.any_bound(action, &self.pressed))
+    }
+    fn any_bound(&self, action: Action, inputs: &HashSet<Input>) -> bool {
+        self.bindings.inputs(action).iter().any(|input| inputs.contains(input))
+    }
+    // Everything GameState::update needs for the next tick. delta_t is the length of a tick.
+    pub fn tick_input(&self, delta_t: f32) -> TickInput {
+        TickInput {
+            forward: self.value(Action::MoveForward),
+            backward: self.value(Action::MoveBackward),
+            strafe_left: self.value(Action::StrafeLeft),
+            strafe_right: self.value(Action::StrafeRight),
+            jump: self.just_pressed(Action::Jump),
+            look: self.look(delta_t),
+        }
+    }
+    // How many degrees to turn this tick, from the mouse motion since the last one and the
+    // position of any look stick. x turns right, y turns down.
+    pub fn look(&self, delta_t: f32) -> (f32, f32) {
+        let analog = &self.bindings.analog;
+        let (mut x, mut y) = (0.0, 0.0);
+        for input in self.bindings.inputs(Action::Look) {
+            match *input {
+                Input::MouseMotion => {
+                    x += analog.mouse_sensitivity * self.look_x;
+                    y += analog.mouse_sensitivity * self.look_y;
+                }
+                Input::GamepadStick(stick) => {
+                    let (stick_x, stick_y) = self.stick(stick);
+                    x += analog.stick_look_speed * delta_t * stick_x;
+                    y -= analog.stick_look_speed * delta_t * stick_y;
+                }
+                _ => {}
+            }
+        }
+        (x, y)
+    }
+    // Folds an input event into the state for the next update.
+    pub fn apply(&mut self, event: &Event) {
+        match *event {
+            Event::KeyPressed { repeat: true, .. } => {}
+            Event::KeyPressed { key, repeat: false } => self.press(Input::Key(key)),
+            Event::KeyReleased { key } => self.release(Input::Key(key)),
+            Event::MouseButtonPressed { button } => self.press(Input::MouseButton(button)),
+            Event::MouseButtonReleased { button } => self.release(Input::MouseButton(button)),
+            Event::GamepadButtonPressed { button } => self.press(Input::GamepadButton(button)),
+            Event::GamepadButtonReleased { button } => self.release(Input::GamepadButton(button)),
+            Event::GamepadAxisMoved { axis, value } => self.axes[axis as usize] = value,
+            Event::GamepadDisconnected => {
+                self.release_all(|input| matches!(input, Input::GamepadButton(_)));
+                self.axes = [0.0; 6];
+            }
+            Event::MouseDelta { dx, dy } => {
+                self.look_x += dx;
+                self.look_y += dy;
+            }
+            // Releases that happen while unfocused never arrive, so let go of the keyboard and
+            // mouse. Pads are polled whether or not the window has focus.
+            Event::FocusLost => {
+                self.release_all(|input| !matches!(input, Input::GamepadButton(_)));
+                self.discard_look();
+            }
+            _ => {}
+        }
+    }
+    // Forgets the mouse motion since the last tick, so that it isn't all applied at once later.
+    pub fn discard_look(&mut self) {
+        self.look_x = 0.0;
+        self.look_y = 0.0;
+    }
+    fn press(&mut self, input: Input) {
+        // A press of something that's already down is an auto-repeat, even if the platform didn't
+        // flag it as one.
+        if self.held.insert(input) {
+            self.pressed.insert(input);
+        }
+    }
+    fn release(&mut self, input: Input) {
+        self.held.remove(&input);
+    }
+    fn release_all(&mut self, which: impl Fn(&Input) -> bool) {
+        let inputs: Vec<Input> = self.held.iter().copied().filter(which).collect();
+        for input in inputs {
+            self.release(input);
+        }
+    }
+    pub fn post_update_reset(&mut self) {
+        self.held_last_tick.clear();
+        for action in Action::ALL {
+            if self.is_held(action) {
+                self.held_last_tick.insert(action);
+            }
+        }
+        self.pressed.clear();
+        self.look_x = 0.0;
+        self.look_y = 0.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn press(key: Key, repeat: bool) -> Event {
+        Event::KeyPressed { key, repeat }
+    }
+
+    #[test]
+    fn holding_jump_fires_once() {
+        let mut input = InputState::new(Bindings::default());
+        input.apply(&press(Key::Space, false));
+        assert!(input.just_pressed(Action::Jump));
+        input.post_update_reset();
+        // Auto-repeats, flagged or not, are still the same press.
+        input.apply(&press(Key::Space, true));
+        input.apply(&press(Key::Space, false));
+        assert!(input.is_held(Action::Jump));
+        assert!(!input.just_pressed(Action::Jump));
+        input.post_update_reset();
+        input.apply(&Event::KeyReleased { key: Key::Space });
+        assert!(!input.is_held(Action::Jump));
+        input.post_update_reset();
+        // Letting go makes the next press a new one.
+        input.apply(&press(Key::Space, false));
+        assert!(input.just_pressed(Action::Jump));
+    }
+
+    #[test]
+    fn tap_within_a_tick_registers() {
+        let mut input = InputState::new(Bindings::default());
+        input.apply(&press(Key::Space, false));
+        input.apply(&Event::KeyReleased { key: Key::Space });
+        assert!(!input.is_held(Action::Jump));
+        assert!(input.just_pressed(Action::Jump));
+        input.post_update_reset();
+        assert!(!input.just_pressed(Action::Jump));
+    }
+
+    #[test]
+    fn second_binding_does_not_press_again() {
+        let mut input = InputState::new(Bindings::default());
+        input.apply(&press(Key::Letter('W'), false));
+        input.post_update_reset();
+        input.apply(&press(Key::Up, false));
+        input.apply(&Event::KeyReleased { key: Key::Letter('W') });
+        assert!(!input.just_pressed(Action::MoveForward));
+        assert!(input.is_held(Action::MoveForward));
+    }
+
+    fn parse(text: &str) -> Result<Bindings, ParseError> {
+        Bindings::parse("test.cfg", text)
+    }
+
+    #[test]
+    fn an_action_takes_several_inputs() {
+        let bindings = parse("jump = Space, mouse_right, pad_b, Space").unwrap();
+        assert_eq!(
+            bindings.inputs(Action::Jump),
+            [
+                Input::Key(Key::Space),
+                Input::MouseButton(MouseButton::Right),
+                Input::GamepadButton(GamepadButton::B),
+            ]
+        );
+        // Actions that aren't listed keep their defaults.
+        assert_eq!(
+            bindings.inputs(Action::MoveForward),
+            Bindings::default().inputs(Action::MoveForward)
+        );
+
+        let mut input = InputState::new(bindings);
+        input.apply(&Event::MouseButtonPressed { button: MouseButton::Right });
+        assert!(input.just_pressed(Action::Jump));
+    }
+
+    #[test]
+    fn an_empty_right_side_unbinds() {
+        let bindings = parse("jump =\nlook =  # no looking around").unwrap();
+        assert!(bindings.inputs(Action::Jump).is_empty());
+        assert!(bindings.inputs(Action::Look).is_empty());
+
+        let mut input = InputState::new(bindings);
+        input.apply(&press(Key::Space, false));
+        assert!(!input.just_pressed(Action::Jump));
+        input.apply(&Event::MouseDelta { dx: 10.0, dy: 0.0 });
+        assert_eq!(input.look(0.01), (0.0, 0.0));
+    }
+
+    #[test]
+    fn mistakes_are_reported_with_their_line() {
+        let error = |text| parse(text).err().map(|e| (e.line, e.message));
+        assert_eq!(
+            error("# Controls\n\nmove_forwards = W"),
+            Some((3, "Unknown action 'move_forwards'".to_string()))
+        );
+        assert_eq!(
+            error("jump = Space\nmove_forward = W, Upp"),
+            Some((2, "Unknown input 'Upp'".to_string()))
+        );
+        assert_eq!(
+            error("jump = mouse"),
+            Some((1, "jump needs a key, button or stick direction".to_string()))
+        );
+        assert_eq!(
+            error("look = mouse\nlook = right_stick"),
+            Some((2, "look is bound more than once".to_string()))
+        );
+        assert_eq!(
+            error("jump Space"),
+            Some((1, "Expected '<action> = <input>, ...'".to_string()))
+        );
+    }
+
+    #[test]
+    fn analog_settings() {
+        let bindings = parse(
+            "dead_zone = 0.1\nresponse_curve = 2\nmouse_sensitivity = 0.5\nstick_look_speed = 90",
+        )
+        .unwrap();
+        assert_eq!(
+            bindings.analog,
+            AnalogSettings {
+                dead_zone: 0.1,
+                response_curve: 2.0,
+                mouse_sensitivity: 0.5,
+                stick_look_speed: 90.0,
+            }
+        );
+
+        let error = |text| parse(text).err().map(|e| (e.line, e.message));
+        assert_eq!(
+            error("dead_zone = 1"),
+            Some((1, "1 is out of range for dead_zone".to_string()))
+        );
+        assert_eq!(
+            error("dead_zone = 0.1\ndead_zone = 0.3"),
+            Some((2, "dead_zone is set more than once".to_string()))
+        );
+        assert_eq!(
+            error("dead_zones = 0.1"),
+            Some((1, "Unknown setting 'dead_zones'".to_string()))
+        );
+    }
+
+    #[test]
+    fn rebinding_takes_effect_on_the_next_tick() {
+        let mut input = InputState::new(Bindings::default());
+        assert_eq!(
+            input.bindings_mut().bind(Action::Jump, Input::MouseMotion),
+            Err("jump needs a key, button or stick direction".to_string())
+        );
+        input.bindings_mut().bind(Action::Jump, Input::MouseButton(MouseButton::Left)).unwrap();
+        input.apply(&Event::MouseButtonPressed { button: MouseButton::Left });
+        assert!(input.tick_input(0.01).jump);
+    }
+}
@@ -1,3 +1,11 @@
+use cgmath::Vector3;
+
+#[derive(Clone, Copy)]
+pub struct Light {
+    pub position: Vector3<f32>,
+    pub color: Vector3<f32>,
+}
+
 #[repr(C)]
 #[derive(bytemuck::Pod, bytemuck::Zeroable, Clone, Copy, Debug)]
 pub(crate) struct LightUniform {
@@ -7,4 +15,13 @@ pub(crate) struct LightUniform {
     pub(crate) color: [f32; 3],
     pub(crate) _padding2: u32,
 }
-
+impl LightUniform {
+    pub fn from_light(light: &Light) -> Self {
+        LightUniform {
+            position: light.position.into(),
+            _padding: 0,
+            color: light.color.into(),
+            _padding2: 0,
+        }
+    }
+}
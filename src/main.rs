@@ -1,433 +1,377 @@
 // This hides the console window when launching cube.exe,
 // at the cost of suppressing println! statements.
 /* #![windows_subsystem = "windows"] */
-#![feature(lazy_cell)]
-
+mod broad_phase;
 mod camera;
+mod capture;
+mod character;
+mod collision;
+mod config;
 mod constants;
+mod events;
 mod game_state;
+mod gamepad;
 mod gpu_state;
+mod input;
 mod light;
 mod model;
 mod physics;
+mod platform;
+mod registry;
+mod replay;
 mod resources;
+mod rigid_body;
 mod rotor;
+mod scene;
+mod simulation;
 mod texture;
 mod time;
+mod time_control;
+mod triple_buffer;
+
+#[cfg(test)]
+mod golden_tests;
 
-use crate::constants::{MIN_TIME_PER_RENDER_FRAME, TIME_PER_GAME_TICK};
-use crate::game_state::{GameState, InputState};
+use crate::capture::{Capture, RECORDING_POLL_INTERVAL};
+use crate::config::Config;
+use crate::game_state::{GameState, RenderSnapshot};
 use crate::gpu_state::WebGPUState;
+use crate::events::{Event, EventBus, Key};
+use crate::gamepad::Gamepad;
+use crate::input::{Bindings, InputState, TickInput, RELOAD_BINDINGS_KEY};
+use crate::platform::{Closer, SystemGamepad, Window};
+use crate::replay::{Recorder, Replay};
+use crate::scene::Scene;
+use crate::time_control::{TimeControl, PAUSED_POLL_INTERVAL};
+use crate::triple_buffer::triple_buffer;
 
-use cgmath::num_traits::abs;
+use anyhow::{anyhow, Context};
 use debug_print::debug_println;
 use pollster::block_on;
-use std::collections::VecDeque;
-use std::mem::{self};
-use std::sync::{mpsc, Arc, Mutex};
-use std::thread::{self};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread::{self, JoinHandle};
 use std::time::{Duration, Instant};
-use windows::Win32::UI::Input::KeyboardAndMouse::{VIRTUAL_KEY, VK_DOWN, VK_LEFT, VK_RIGHT, VK_SPACE, VK_UP};
-use windows::Win32::{Foundation::POINT, System::LibraryLoader::GetModuleHandleA};
-use windows::{
-    core::*,
-    Win32::{
-        Foundation::{HWND, LPARAM, LRESULT, RECT, WPARAM},
-        Graphics::Gdi::ValidateRect,
-        UI::WindowsAndMessaging::*,
-    },
-};
-
-const EVENT_QUEUE_SIZE_IN_BYTES: i32 = std::mem::size_of::<*mut Arc<Mutex<EventQueue>>>() as i32;
-
-fn main() -> windows::core::Result<()> {
-    let hinstance = unsafe { GetModuleHandleA(None) }?;
-    let window_class_name = s!("window");
-    let wc = WNDCLASSA {
-        hCursor: unsafe { LoadCursorW(None, IDC_APPSTARTING) }?,
-        hInstance: hinstance.into(),
-        lpszClassName: window_class_name,
-        style: CS_HREDRAW | CS_VREDRAW,
-        lpfnWndProc: Some(wndproc),
-        cbWndExtra: 2 * EVENT_QUEUE_SIZE_IN_BYTES,
-        ..Default::default()
-    };
-    let atom = unsafe { RegisterClassA(&wc) };
-    debug_assert!(atom != 0);
 
-    const WINDOW_INITIAL_WIDTH: i32 = 2560;
-    const WINDOW_INITIAL_HEIGHT: i32 = 1440;
-    // const WINDOW_INITIAL_WIDTH: i32 = 1920;
-    // const WINDOW_INITIAL_HEIGHT: i32 = 1080;
-    let window = unsafe {
-        CreateWindowExA(
-            WINDOW_EX_STYLE::default(),
-            window_class_name,
-            s!("My sample window"),
-            WS_VISIBLE | WS_POPUP,
-            CW_USEDEFAULT,
-            CW_USEDEFAULT,
-            WINDOW_INITIAL_WIDTH,
-            WINDOW_INITIAL_HEIGHT,
-            None,
-            None,
-            hinstance,
-            None,
-        )
+fn main() -> anyhow::Result<()> {
+    let config = block_on(Config::load(std::env::args().skip(1)))?;
+    let capture_options = &config.capture;
+    if let Some(path) = &capture_options.replay {
+        return run_replay(path);
+    }
+    let mut scene = block_on(Scene::load(&config.scene))?;
+    if let Some(fov) = config.fov {
+        scene.camera.fovy = fov;
+    }
+    if let Some((width, height)) = capture_options.headless {
+        return run_headless(width, height, &scene, &config);
+    }
+    let bindings_file = config.bindings.clone();
+    let mouse_sensitivity = config.mouse_sensitivity;
+    let bindings = load_bindings(&bindings_file, mouse_sensitivity)?;
+    let mut recorder = match &capture_options.record_input {
+        Some(path) => {
+            let scene_text = block_on(resources::load_string(&config.scene))?;
+            Some(Recorder::create(path, &scene_text, config.tuning)?)
+        }
+        None => None,
     };
 
-    // These will get manipulated directly by wndproc.
-    let gpu_event_queue = Arc::new(Mutex::new(EventQueue::new()));
-    let input_event_queue = Arc::new(Mutex::new(EventQueue::new()));
-    unsafe {
-        SetWindowLongPtrA(
-            window,
-            WINDOW_LONG_PTR_INDEX(0),
-            &gpu_event_queue as *const Arc<Mutex<EventQueue>> as isize,
-        )
-    };
-    unsafe {
-        SetWindowLongPtrA(
-            window,
-            WINDOW_LONG_PTR_INDEX(EVENT_QUEUE_SIZE_IN_BYTES),
-            &input_event_queue as *const Arc<Mutex<EventQueue>> as isize,
-        )
-    };
+    // The platform publishes input and window events to both threads.
+    let mut event_bus = EventBus::new();
+    let gpu_events = event_bus.subscribe();
+    let game_events = event_bus.subscribe();
+    let mut window = Window::new(config.width, config.height, config.window_mode, event_bus)?;
 
-    // Set sleep granularity to 1ms.
-    unsafe { windows::Win32::Media::timeBeginPeriod(1) };
+    let _timer_period = platform::begin_timer_period();
 
-    let mut game_state = GameState::new(WINDOW_INITIAL_WIDTH as f32 / WINDOW_INITIAL_HEIGHT as f32);
-    let mut gpu_state: WebGPUState = block_on(WebGPUState::new(window, hinstance.into(), game_state.clone()));
-    let mut input_state = InputState::new();
-    let (tx, rx) = mpsc::channel();
-    macro_rules! printUnexpected {
-        ($event_name:expr) => {
-            debug_println!(
-                "Unexpected occurrence: {} event was created with incorrect EventData",
-                $event_name
-            );
-        };
-    }
-    {
-        let gpu_event_queue = Arc::clone(&gpu_event_queue);
-        let _gpu_thread = thread::spawn(move || {
-            let mut last_render = Instant::now();
-            let _ = gpu_state.render();
+    // Borderless windows aren't the configured size, so the aspect ratio comes from the window.
+    let rect = window.client_rect();
+    let aspect_ratio = rect.width() as f32 / rect.height() as f32;
+    let mut game_state = GameState::from_scene(&scene, aspect_ratio, config.tuning);
+    let mut gpu_state: WebGPUState =
+        block_on(WebGPUState::new(&window, &config.graphics, game_state.clone()));
+    let time_per_render_frame = config.time_per_render_frame();
+    let time_per_tick = config.tuning.time_per_tick();
+    let mut input_state = InputState::new(bindings);
+    let (mut snapshot_writer, mut snapshot_reader) =
+        triple_buffer(game_state.snapshot(Instant::now(), time_per_tick));
+    let closer = window.closer();
+    let mut capture = Capture::new(capture_options, time_per_tick);
+    let ticks_per_recorded_frame = capture.ticks_per_frame();
+    let mut recording = capture.is_recording();
+    // While recording, the GPU thread saves every snapshot as a frame and then tells the game
+    // thread whether it wants another, which the game only ticks toward once it does.
+    let (frame_saved_sender, frame_saved_receiver) = mpsc::channel();
+    let gpu_thread = spawn("GPU", closer.clone(), move || {
+        let mut last_render = Instant::now();
+        gpu_state.render()?;
+        // The starting snapshot is the first recorded frame.
+        let mut unrecorded_snapshot = true;
 
-            let mut last_fps_print = last_render;
-            let mut frames = 0;
-            loop {
-                {
-                    let mut queue = gpu_event_queue.lock().unwrap();
-                    while !(*queue).is_empty() {
-                        let event = (*queue).pop_front().expect("queue somehow empty?");
-                        match event.message {
-                            WM_MOUSEMOVE => match event.data {
-                                EventData::MouseMoveData(point) => {
-                                    gpu_state.update_bg_color(&point);
-                                }
-                                _ => {
-                                    printUnexpected!("WM_MOUSEMOVE");
-                                }
-                            },
-                            WM_PAINT => match event.data {
-                                EventData::EmptyData() => {}
-                                _ => {
-                                    printUnexpected!("WM_PAINT");
-                                }
-                            },
-                            WM_SIZE => match event.data {
-                                EventData::ResizeData(rect) => {
-                                    gpu_state.resize(rect);
-                                }
-                                _ => {
-                                    printUnexpected!("WM_SIZE");
-                                }
-                            },
-                            _ => (),
-                        }
-                    }
-                }
-                let mut game_state_res = rx.try_recv();
-                if game_state_res.is_ok() {
-                    let mut next = rx.try_recv();
-                    while next.is_ok() {
-                        game_state_res = next;
-                        next = rx.try_recv();
+        let mut last_fps_print = last_render;
+        let mut frames = 0;
+        // The snapshot before the reader's current one, and the blend of the two that gets
+        // drawn.
+        let mut previous_snapshot = snapshot_reader.read().clone();
+        let mut frame_snapshot = snapshot_reader.read().clone();
+        loop {
+            for event in gpu_events.try_iter() {
+                match event {
+                    Event::MouseMoved(point) => gpu_state.update_bg_color(&point),
+                    Event::Resized(rect) => gpu_state.resize(rect),
+                    Event::KeyPressed { key: Key::F(12), .. } => capture.request_screenshot(),
+                    Event::Quit => {
+                        // The surface goes away with the window once this thread is joined.
+                        gpu_state.finish();
+                        return Ok(());
                     }
-                    let game_state: GameState = game_state_res.unwrap();
-                    gpu_state.update_camera(game_state.get_camera());
-                }
-                if Instant::now() >= last_fps_print + Duration::from_secs(2) {
-                    debug_println!("FPS = {}", frames as f32 / 2.0);
-                    frames = 0;
-                    last_fps_print = Instant::now();
+                    _ => (),
                 }
-                let next = Instant::now();
-                if next >= last_render + *MIN_TIME_PER_RENDER_FRAME {
-                    last_render = next;
-                    frames += 1;
-                    let _ = gpu_state.render();
+            }
+            if snapshot_reader.is_fresh() {
+                previous_snapshot.copy_from(snapshot_reader.read());
+                snapshot_reader.update();
+                unrecorded_snapshot = true;
+            }
+            if Instant::now() >= last_fps_print + Duration::from_secs(2) {
+                debug_println!("FPS = {}", frames as f32 / 2.0);
+                frames = 0;
+                last_fps_print = Instant::now();
+            }
+            let next = Instant::now();
+            let recording = capture.is_recording();
+            let due = if recording {
+                std::mem::take(&mut unrecorded_snapshot)
+            } else {
+                next >= last_render + time_per_render_frame
+            };
+            if due {
+                last_render = next;
+                frames += 1;
+                if recording {
+                    // Recorded frames are the ticks themselves, not blends timed by the clock.
+                    frame_snapshot.copy_from(snapshot_reader.read());
                 } else {
-                    let time_to_next_frame = last_render + *MIN_TIME_PER_RENDER_FRAME - next;
-                    if time_to_next_frame > Duration::from_micros(1500) {
-                        thread::sleep(Duration::from_millis(
-                            time_to_next_frame.as_millis() as u64 - 1,
-                        ));
+                    RenderSnapshot::interpolate(
+                        &previous_snapshot,
+                        snapshot_reader.read(),
+                        next,
+                        &mut frame_snapshot,
+                    );
+                }
+                gpu_state.update_camera(frame_snapshot.camera);
+                gpu_state.update_time(frame_snapshot.time);
+                gpu_state.update_instances(&frame_snapshot);
+                if capture.wants_frame() {
+                    match capture_frame(&mut gpu_state, &mut capture) {
+                        Ok(path) => println!("Saved {}", path.display()),
+                        Err(e) => {
+                            println!("Capture failed: {e:#}");
+                            capture.cancel();
+                        }
+                    }
+                    if recording {
+                        // The game thread is gone if it failed, and the window with it.
+                        let _ = frame_saved_sender.send(capture.is_recording());
                     }
+                } else {
+                    gpu_state.render()?;
+                }
+            } else if recording {
+                thread::sleep(RECORDING_POLL_INTERVAL);
+            } else {
+                let time_to_next_frame = last_render + time_per_render_frame - next;
+                if time_to_next_frame > Duration::from_micros(1500) {
+                    thread::sleep(Duration::from_millis(time_to_next_frame.as_millis() as u64 - 1));
                 }
             }
-        });
-    }
-    {
-        let input_event_queue = Arc::clone(&input_event_queue);
-        let _game_thread = thread::spawn(move || {
-            let mut last_tick = Instant::now();
-            let mut game_rect: RECT = unsafe { mem::zeroed() };
-            let _ = unsafe { GetClientRect(window, &mut game_rect) };
-            loop {
-                {
-                    let mut queue = input_event_queue.lock().unwrap();
-                    while !(*queue).is_empty() {
-                        let event = (*queue).pop_front().expect("queue somehow empty?");
-                        match event.message {
-                            WM_KEYDOWN => match event.data {
-                                EventData::KeyDownData(wparam, lparam) => {
-                                    let virtual_key = VIRTUAL_KEY(wparam.0 as u16);
-                                    match virtual_key {
-                                        VK_LEFT => {
-                                            // TODO: this does not work as expected. Some internet
-                                            // discussions suggest that maybe I need to wait longer
-                                            // to see these fields get populated?
-                                            // https://stackoverflow.com/questions/44897991/wm-keydown-repeat-count
-                                            let key_flags = lparam.0 as u32;
-                                            let was_key_already_down: bool =
-                                                (key_flags & KF_REPEAT) == KF_REPEAT;
-                                            if !was_key_already_down {
-                                                input_state.left = true;
-                                            }
-                                        }
-                                        VK_RIGHT => {
-                                            let key_flags = lparam.0 as u32;
-                                            let was_key_already_down: bool =
-                                                (key_flags & KF_REPEAT) == KF_REPEAT;
-                                            if !was_key_already_down {
-                                                input_state.right = true;
-                                            }
-                                        }
-                                        VK_UP => {
-                                            input_state.forward = true;
-                                        }
-                                        VK_DOWN => {
-                                            input_state.backward = true;
-                                        }
-                                        VK_SPACE => {
-                                            input_state.jump = true;
-                                        }
-                                        _ => {}
-                                    }
-                                }
-                                _ => {
-                                    printUnexpected!("WM_KEYDOWN");
-                                }
-                            },
-                            WM_KEYUP => match event.data {
-                                EventData::KeyUpData(wparam, _lparam) => {
-                                    let virtual_key = VIRTUAL_KEY(wparam.0 as u16);
-                                    match virtual_key {
-                                        VK_LEFT => {
-                                            input_state.left = false;
-                                        }
-                                        VK_RIGHT => {
-                                            input_state.right = false;
-                                        }
-                                        VK_UP => {
-                                            input_state.forward = false;
-                                        }
-                                        VK_DOWN => {
-                                            input_state.backward = false;
-                                        }
-                                        _ => {}
-                                    }
-                                }
-                                _ => {
-                                    printUnexpected!("WM_KEYUP");
-                                }
-                            },
-                            WM_MOUSEMOVE => match event.data {
-                                EventData::MouseMoveData(pt) => {
-                                    let center_x = (game_rect.right + game_rect.left) / 2;
-                                    let center_y = (game_rect.bottom + game_rect.top) / 2;
-                                    let near_center_x = abs(pt.x - center_x)
-                                        < (game_rect.right - game_rect.left) / 4;
-                                    let near_center_y = abs(pt.y - center_y)
-                                        < (game_rect.bottom - game_rect.top) / 4;
-                                    if near_center_x && near_center_y {
-                                        input_state.mouse_x += pt.x - center_x;
-                                        input_state.mouse_y += pt.y - center_y;
-                                    } else  {
-                                        debug_println!("Detected mouse outside of central box. Mouse entering window for first time?");
-                                    }
-                                }
-                                _ => {
-                                    printUnexpected!("WM_MOUSEMOVE");
-                                }
-                            },
-                            WM_SIZE => match event.data {
-                                EventData::ResizeData(rect) => {
-                                    game_rect = rect;
-                                    let width = rect.right - rect.left;
-                                    let height = rect.bottom - rect.top;
-                                    game_state.change_camera_aspect(width as f32 / height as f32);
-                                }
-                                _ => {
-                                    printUnexpected!("WM_SIZE");
-                                }
-                            },
-                            _ => (),
+        }
+    })?;
+    let game_thread = spawn("game", closer, move || {
+        let mut time_control = TimeControl::new(time_per_tick, Instant::now());
+        // Pads don't send messages, so the game thread polls them itself.
+        let mut gamepad = SystemGamepad::new();
+        let mut gamepad_events = vec![];
+        if recording {
+            time_control.pause();
+        }
+        loop {
+            for event in game_events.try_iter() {
+                match event {
+                    // Minimized windows have no size, and keep the aspect they had.
+                    Event::Resized(rect) if rect.width() > 0 && rect.height() > 0 => {
+                        game_state.change_camera_aspect(rect.width() as f32 / rect.height() as f32);
+                    }
+                    Event::Resized(_) => {}
+                    Event::Quit => return Ok(()),
+                    Event::KeyPressed { key: RELOAD_BINDINGS_KEY, repeat: false } => {
+                        // A broken file keeps the bindings there were.
+                        match load_bindings(&bindings_file, mouse_sensitivity) {
+                            Ok(bindings) => {
+                                *input_state.bindings_mut() = bindings;
+                                println!("Reloaded {bindings_file}");
+                            }
+                            Err(e) => println!("Could not reload {bindings_file}: {e:#}"),
                         }
                     }
+                    // Recording decides when ticks run, so the time controls wait until it's done.
+                    event if !recording && time_control.handle(&event, Instant::now()) => {
+                        debug_println!(
+                            "Time scale {}{}",
+                            time_control.time_scale(),
+                            if time_control.is_paused() { ", paused" } else { "" }
+                        );
+                    }
+                    event => input_state.apply(&event),
                 }
-                let current_time = Instant::now();
-                // I am assuming it's rare or impossible for current_time - last_tick to be more
-                // than two frames. That means I expect this loop to always run to 0 or 1
-                // iterations.
-                //
-                // If the time window does span multiple frames, I just pass the same input on
-                // every frame.
-                while current_time - last_tick >= *TIME_PER_GAME_TICK {
-                    last_tick = last_tick + *TIME_PER_GAME_TICK;
-                    game_state.update(&input_state, last_tick);
-                    input_state.post_update_reset();
+            }
+            for wants_another in frame_saved_receiver.try_iter() {
+                if wants_another {
+                    for _ in 0..ticks_per_recorded_frame {
+                        time_control.step();
+                    }
+                } else {
+                    recording = false;
+                    time_control.resume(Instant::now());
                 }
-                let _ = tx.send(game_state.clone());
-
-                let time_to_next_tick = last_tick + *TIME_PER_GAME_TICK - Instant::now();
-                if time_to_next_tick > Duration::from_micros(1500) {
-                    thread::sleep(Duration::from_millis(time_to_next_tick.as_millis() as u64 - 1));
+            }
+            gamepad.poll(&mut gamepad_events);
+            for event in gamepad_events.drain(..) {
+                input_state.apply(&event);
+            }
+            let current_time = Instant::now();
+            // I am assuming it's rare or impossible for current_time - last_tick to be more
+            // than two frames. That means I expect this loop to always run to 0 or 1
+            // iterations.
+            //
+            // If the time window does span multiple frames, I just pass the same input on
+            // every frame.
+            let mut ticked = false;
+            while time_control.next_tick(current_time) {
+                let input = input_state.tick_input(time_per_tick.as_secs_f32());
+                game_state.update(&input);
+                input_state.post_update_reset();
+                let position = game_state.player_position();
+                if let Some(Err(e)) = recorder.as_mut().map(|r| r.record(&input, position)) {
+                    println!("Input recording failed: {e:#}");
+                    recorder = None;
                 }
+                ticked = true;
+            }
+            if ticked {
+                game_state.write_snapshot(
+                    snapshot_writer.back_mut(),
+                    time_control.last_tick(),
+                    time_control.real_time_per_tick(),
+                );
+                snapshot_writer.publish();
+            }
+            if time_control.is_paused() && !recording {
+                // Looking around while paused would turn the camera all at once on the next tick.
+                input_state.discard_look();
             }
-        });
-    }
 
-    let mut message = MSG::default();
-    unsafe {
-        while GetMessageA(&mut message, None, 0, 0).into() {
-            DispatchMessageA(&message);
+            let time_to_next_tick =
+                time_control.time_to_next_tick(Instant::now()).unwrap_or(PAUSED_POLL_INTERVAL);
+            if time_to_next_tick > Duration::from_micros(1500) {
+                thread::sleep(Duration::from_millis(time_to_next_tick.as_millis() as u64 - 1));
+            }
         }
-    }
+    })?;
 
-    Ok(())
+    let result = window.run();
+    // Both threads stop on the Quit that run publishes as it returns. The window is only destroyed
+    // after they have, so the GPU thread never draws to a surface that is gone.
+    let gpu_result = join("GPU", gpu_thread);
+    let game_result = join("game", game_thread);
+    drop(window);
+    result.and(gpu_result).and(game_result)
 }
 
-#[derive(Clone, Copy)]
-struct WindowsEvent {
-    message: u32,
-    data: EventData,
+// Closes the window when dropped, which ends the main loop and, through it, the other thread.
+struct CloseOnExit(Closer);
+impl Drop for CloseOnExit {
+    fn drop(&mut self) {
+        self.0.close();
+    }
 }
 
-#[derive(Clone, Copy)]
-enum EventData {
-    ResizeData(RECT),
-    EmptyData(),
-    MouseMoveData(POINT),
-    KeyDownData(WPARAM, LPARAM),
-    KeyUpData(WPARAM, LPARAM),
+// Runs `body` on a new thread. However the thread ends, by returning, failing or panicking, the
+// window is closed, so that nothing waits on a thread that is gone.
+fn spawn(
+    name: &str,
+    closer: Closer,
+    body: impl FnOnce() -> anyhow::Result<()> + Send + 'static,
+) -> anyhow::Result<JoinHandle<anyhow::Result<()>>> {
+    let thread = thread::Builder::new().name(format!("{name} thread")).spawn(move || {
+        let _close = CloseOnExit(closer);
+        body()
+    })?;
+    Ok(thread)
 }
 
-type EventQueue = VecDeque<WindowsEvent>;
+fn join(name: &str, thread: JoinHandle<anyhow::Result<()>>) -> anyhow::Result<()> {
+    thread
+        .join()
+        .map_err(|_| anyhow!("The {name} thread panicked"))?
+        .with_context(|| format!("The {name} thread failed"))
+}
 
-extern "system" fn wndproc(window: HWND, message: u32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
-    let gpu_queue_ptr = unsafe { GetWindowLongPtrA(window, WINDOW_LONG_PTR_INDEX(0)) }
-        as *mut Arc<Mutex<EventQueue>>;
-    let input_queue_ptr =
-        unsafe { GetWindowLongPtrA(window, WINDOW_LONG_PTR_INDEX(EVENT_QUEUE_SIZE_IN_BYTES)) }
-            as *mut Arc<Mutex<EventQueue>>;
-    if gpu_queue_ptr.is_null() || input_queue_ptr.is_null() {
-        debug_println!("Exiting wndproc early due to null event queues.");
-        return unsafe { DefWindowProcA(window, message, wparam, lparam) };
+// Loads the bindings file, with the mouse sensitivity given on the command line or in the config
+// taking precedence over the file's.
+fn load_bindings(file_name: &str, mouse_sensitivity: Option<f32>) -> anyhow::Result<Bindings> {
+    let mut bindings = block_on(Bindings::load(file_name))?;
+    if let Some(sensitivity) = mouse_sensitivity {
+        bindings.analog.mouse_sensitivity = sensitivity;
     }
-    match message {
-        WM_PAINT => {
-            debug_println!("WM_PAINT");
-            {
-                let mut queue = unsafe { (*gpu_queue_ptr).lock().unwrap() };
-                (*queue).push_back(WindowsEvent { message, data: EventData::EmptyData() });
-            }
-            unsafe { ValidateRect(window, None) };
-            LRESULT(0)
-        }
-        WM_DESTROY => {
-            debug_println!("WM_DESTROY");
-            unsafe { PostQuitMessage(0) };
-            LRESULT(0)
-        }
-        WM_SIZE => {
-            debug_println!("WM_SIZE");
-            let mut rect: RECT = unsafe { mem::zeroed() };
-            let _ = unsafe { GetClientRect(window, &mut rect) };
-            let event = WindowsEvent { message, data: EventData::ResizeData(rect) };
-            {
-                let mut gpu_queue = unsafe { (*gpu_queue_ptr).lock().unwrap() };
-                (*gpu_queue).push_back(event);
-            }
-            {
-                let mut input_queue = unsafe { (*input_queue_ptr).lock().unwrap() };
-                (*input_queue).push_back(event);
-            }
-            LRESULT(0)
-        }
-        WM_MOUSEACTIVATE => {
-            debug_println!("WM_MOUSEACTIVATE");
-            LRESULT(0)
-        }
-        WM_MOUSEMOVE => {
-            // debug_println!("WM_MOUSEMOVE");
-            let mut pt: POINT = unsafe { mem::zeroed() };
-            let _ = unsafe { GetCursorPos(&mut pt) };
+    Ok(bindings)
+}
 
-            let mut gpu_queue = unsafe { (*gpu_queue_ptr).lock().unwrap() };
-            (*gpu_queue).push_back(WindowsEvent { message, data: EventData::MouseMoveData(pt) });
-            let mut input_queue = unsafe { (*input_queue_ptr).lock().unwrap() };
-            (*input_queue).push_back(WindowsEvent { message, data: EventData::MouseMoveData(pt) });
+// Renders a frame and saves it for the screenshot or recording that asked for one. Returns the path
+// of the saved frame.
+fn capture_frame(gpu_state: &mut WebGPUState, capture: &mut Capture) -> anyhow::Result<PathBuf> {
+    gpu_state.set_fixed_time(capture.frame_time());
+    let frame = gpu_state.render_to_rgba()?;
+    let (width, height) = gpu_state.size();
+    let path = capture.save_frame(width, height, frame)?;
+    if !capture.is_recording() {
+        gpu_state.set_fixed_time(None);
+    }
+    Ok(path)
+}
 
-            let mut rect: RECT = unsafe { mem::zeroed() };
-            let _ = unsafe { GetClientRect(window, &mut rect) };
-            let center_x = (rect.right + rect.left) / 2;
-            let center_y = (rect.bottom + rect.top) / 2;
-            unsafe {
-                let _ = SetCursorPos(center_x, center_y);
-            }
+// Plays back an input recording without a window or GPU, and fails if the player doesn't end up
+// where they did when it was recorded.
+fn run_replay(path: &Path) -> anyhow::Result<()> {
+    let replay = Replay::load(path)?;
+    replay.verify()?;
+    println!("Replayed {} ticks from {}; every position matches", replay.ticks(), path.display());
+    Ok(())
+}
 
-            LRESULT(0)
-        }
-        WM_KEYDOWN => {
-            {
-                let mut queue = unsafe { (*input_queue_ptr).lock().unwrap() };
-                (*queue).push_back(WindowsEvent {
-                    message,
-                    data: EventData::KeyDownData(wparam, lparam),
-                });
-            }
-            LRESULT(0)
+// Renders the scene offscreen, without a window or game thread, and records the requested frames.
+// The game runs without input between frames, the same number of ticks as a windowed recording.
+fn run_headless(
+    width: u32,
+    height: u32,
+    scene: &Scene,
+    config: &Config,
+) -> anyhow::Result<()> {
+    let mut game_state = GameState::from_scene(scene, width as f32 / height as f32, config.tuning);
+    let mut gpu_state =
+        block_on(WebGPUState::new_headless(width, height, &config.graphics, game_state.clone()))?;
+    let time_per_tick = config.tuning.time_per_tick();
+    let mut capture = Capture::new(&config.capture, time_per_tick);
+    while capture.is_recording() {
+        let snapshot = game_state.snapshot(Instant::now(), time_per_tick);
+        gpu_state.update_camera(snapshot.camera);
+        gpu_state.update_instances(&snapshot);
+        let path = capture_frame(&mut gpu_state, &mut capture)?;
+        println!("Saved {}", path.display());
+        for _ in 0..capture.ticks_per_frame() {
+            game_state.update(&TickInput::default());
         }
-        WM_KEYUP => {
-            debug_println!("WM_KEYUP");
-            {
-                let mut queue = unsafe { (*input_queue_ptr).lock().unwrap() };
-                (*queue).push_back(WindowsEvent {
-                    message,
-                    data: EventData::KeyUpData(wparam, lparam),
-                });
-            }
-            LRESULT(0)
-        }
-        WM_SETCURSOR => unsafe {
-            // debug_println!("WM_SETCURSOR");
-            SetCursor(HCURSOR { 0: 0 });
-            LRESULT(0)
-        },
-        _ => unsafe { DefWindowProcA(window, message, wparam, lparam) },
     }
+    Ok(())
 }
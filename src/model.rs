@@ -1,8 +1,12 @@
-use crate::resources;
+use crate::{
+    physics::{ColliderKind, Collision},
+    registry::ModelAsset,
+    resources,
+};
 
-use anyhow::Result;
-use std::io::Cursor;
-use std::{io::BufReader, mem::size_of};
+use anyhow::{Context, Result};
+use cgmath::Vector3;
+use std::mem::size_of;
 use wgpu::util::DeviceExt;
 use wgpu::{
     BindGroupLayout, Buffer, BufferAddress, Device, Queue, VertexAttribute, VertexBufferLayout,
@@ -17,20 +21,77 @@ pub struct Model {
 }
 
 pub struct Material {
+    #[allow(unused)]
     pub name: String,
+    #[allow(unused)]
     pub diffuse_texture: texture::Texture,
     pub bind_group: wgpu::BindGroup,
 }
 
 pub struct Mesh {
+    #[allow(unused)]
     pub name: String,
     pub vertex_buffer: Buffer,
     pub index_buffer: Buffer,
     pub num_elements: u32,
     pub material: Option<usize>,
+    #[allow(unused)]
     pub raw_vertices: Vec<ModelVertex>,
+    #[allow(unused)]
     pub raw_indices: Vec<u32>,
 }
+impl Mesh {
+    fn new(device: &Device, raw: &RawMesh) -> Self {
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("{:?} Vertex Buffer", raw.name)),
+            contents: bytemuck::cast_slice(&raw.vertices),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("{:?} Index Buffer", raw.name)),
+            contents: bytemuck::cast_slice(&raw.indices),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+        Mesh {
+            name: raw.name.clone(),
+            vertex_buffer,
+            index_buffer,
+            num_elements: raw.indices.len() as u32,
+            material: raw.material,
+            raw_vertices: raw.vertices.clone(),
+            raw_indices: raw.indices.clone(),
+        }
+    }
+}
+
+// A model as loaded from its asset, before any of it is on the GPU. The renderer uploads it and
+// collision shapes are built from it, so what is drawn and what is collided with can't differ.
+#[derive(Clone, Debug)]
+pub struct Geometry {
+    pub meshes: Vec<RawMesh>,
+    pub materials: Vec<tobj::Material>,
+}
+impl Geometry {
+    // A collision shape around every mesh of the model.
+    pub fn collision(&self, kind: ColliderKind) -> Collision {
+        let mut positions = vec![];
+        let mut indices = vec![];
+        for mesh in &self.meshes {
+            let first = positions.len() as u32;
+            positions.extend(mesh.vertices.iter().map(|vertex| Vector3::from(vertex.position)));
+            indices.extend(mesh.indices.iter().map(|index| first + index));
+        }
+        Collision { kind, ..Collision::new(positions, indices) }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RawMesh {
+    pub name: String,
+    pub vertices: Vec<ModelVertex>,
+    pub indices: Vec<u32>,
+    pub material: Option<usize>,
+}
 pub trait DescribeVB {
     fn describe_vb() -> VertexBufferLayout<'static>;
 }
@@ -64,7 +125,8 @@ impl DescribeVB for ModelVertex {
     }
 }
 
-pub fn cube_mesh(device: &Device, inverted: bool) -> Mesh {
+// The builtin cube, which fills -1..1 on every axis. Inverted cubes face inward.
+fn cube_mesh(inverted: bool) -> RawMesh {
     let vertices: Vec<ModelVertex> =
         [
             ModelVertex {
@@ -194,80 +256,27 @@ pub fn cube_mesh(device: &Device, inverted: bool) -> Mesh {
         18, 18, 17, 19, 20, 22, 21, 21, 22, 23,
     ];
     if inverted { indices.reverse(); }
-    let name = "Simple_Cube";
-    let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-        label: Some(&format!("{:?} Vertex Buffer", name)),
-        contents: bytemuck::cast_slice(&vertices),
-        usage: wgpu::BufferUsages::VERTEX,
-    });
-    let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-        label: Some(&format!("{:?} Index Buffer", name)),
-        contents: bytemuck::cast_slice(&indices),
-        usage: wgpu::BufferUsages::INDEX,
-    });
-    Mesh {
-        name: name.to_string(),
-        vertex_buffer,
-        index_buffer,
-        num_elements: indices.len() as u32,
-        material: None,
-        raw_vertices: vertices,
-        raw_indices: indices.into(),
-    }
-}
-
-pub fn cube_model(device: &Device) -> Model {
-    Model { materials: vec![], meshes: vec![cube_mesh(device, false)] }
+    RawMesh { name: "Simple_Cube".to_string(), vertices, indices: indices.into(), material: None }
 }
 
-pub fn double_cube_model(device: &Device) -> Model {
-    Model {
-        materials: vec![],
-        meshes: vec![cube_mesh(device, true), cube_mesh(device, false)],
+// Loads what a model named in a scene file looks like.
+pub fn load_geometry(asset: &ModelAsset) -> Result<Geometry> {
+    match asset {
+        ModelAsset::Obj(file_name) => {
+            load_obj(file_name).with_context(|| format!("Could not load model {file_name}"))
+        }
+        ModelAsset::Cube => Ok(Geometry { meshes: vec![cube_mesh(false)], materials: vec![] }),
+        ModelAsset::DoubleCube => {
+            Ok(Geometry { meshes: vec![cube_mesh(true), cube_mesh(false)], materials: vec![] })
+        }
     }
 }
 
-pub async fn load_model(
-    file_name: &str,
-    device: &Device,
-    queue: &Queue,
-    layout: &BindGroupLayout,
-) -> Result<Model> {
-    let obj_text = resources::load_string(file_name).await?;
-    let obj_cursor = Cursor::new(obj_text);
-    let mut obj_reader = BufReader::new(obj_cursor);
-
-    let (models, obj_materials) = tobj::load_obj_buf_async(
-        &mut obj_reader,
+fn load_obj(file_name: &str) -> Result<Geometry> {
+    let (models, materials) = tobj::load_obj(
+        resources::asset_path(file_name),
         &tobj::LoadOptions { triangulate: true, single_index: true, ..Default::default() },
-        |p| async move {
-            let mat_text = resources::load_string(&p).await.unwrap();
-            tobj::load_mtl_buf(&mut BufReader::new(Cursor::new(mat_text)))
-        },
-    )
-    .await?;
-
-    let mut materials: Vec<Material> = Vec::new();
-    for m in obj_materials? {
-        let diffuse_texture =
-            resources::load_texture(&(m.diffuse_texture.unwrap()), device, queue).await?;
-        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&diffuse_texture.view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&diffuse_texture.sampler),
-                },
-            ],
-            label: None,
-        });
-
-        materials.push(Material { name: m.name, diffuse_texture, bind_group })
-    }
+    )?;
 
     let meshes = models
         .into_iter()
@@ -287,29 +296,48 @@ pub async fn load_model(
                     ],
                 })
                 .collect::<Vec<_>>();
-
-            let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some(&format!("{:?} Vertex Buffer", file_name)),
-                contents: bytemuck::cast_slice(&vertices),
-                usage: wgpu::BufferUsages::VERTEX,
-            });
-            let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some(&format!("{:?} Index Buffer", file_name)),
-                contents: bytemuck::cast_slice(&m.mesh.indices),
-                usage: wgpu::BufferUsages::INDEX,
-            });
-
-            Mesh {
+            RawMesh {
                 name: file_name.to_string(),
-                vertex_buffer,
-                index_buffer,
-                num_elements: m.mesh.indices.len() as u32,
+                vertices,
+                indices: m.mesh.indices,
                 material: m.mesh.material_id,
-                raw_vertices: vertices,
-                raw_indices: m.mesh.indices.clone(),
             }
         })
         .collect::<Vec<_>>();
 
+    Ok(Geometry { meshes, materials: materials? })
+}
+
+// Puts a model's geometry on the GPU, along with the textures its materials use.
+pub async fn load_model(
+    geometry: &Geometry,
+    device: &Device,
+    queue: &Queue,
+    layout: &BindGroupLayout,
+) -> Result<Model> {
+    let mut materials: Vec<Material> = Vec::new();
+    for m in &geometry.materials {
+        let diffuse_texture =
+            resources::load_texture(m.diffuse_texture.as_ref().unwrap(), device, queue).await?;
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&diffuse_texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&diffuse_texture.sampler),
+                },
+            ],
+            label: None,
+        });
+
+        materials.push(Material { name: m.name.clone(), diffuse_texture, bind_group })
+    }
+
+    let meshes = geometry.meshes.iter().map(|mesh| Mesh::new(device, mesh)).collect();
+
     Ok(Model { meshes, materials })
 }
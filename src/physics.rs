@@ -1,6 +1,14 @@
-use cgmath::{InnerSpace, One, Point3, Quaternion, Vector3, Zero};
+use cgmath::{
+    EuclideanSpace, InnerSpace, Matrix, Matrix3, One, Point3, Quaternion, SquareMatrix, Vector3,
+    Zero,
+};
 
-use crate::constants::GRAVITY;
+use crate::{
+    collision::{Aabb, Hull},
+    constants::DEFAULT_GRAVITY,
+};
+
+use std::collections::HashMap;
 
 #[derive(Clone)]
 pub struct Physics {
@@ -8,7 +16,10 @@ pub struct Physics {
     pub velocity: Vector3<f32>,
     pub accel: Vector3<f32>,
     pub mass: f32,
+    // The inverse of the inertia tensor, in body space, so it stays the same as the body turns.
+    pub inverse_inertia: Matrix3<f32>,
     pub angular_position: Quaternion<f32>,
+    // In radians per second, about the axis it points along.
     pub angular_velocity: Vector3<f32>,
     pub angular_accel: Vector3<f32>,
     pub collision: Collision,
@@ -18,8 +29,9 @@ impl Physics {
         Physics {
             position: Point3::new(0.0, 0.0, 0.0),
             velocity: Vector3::zero(),
-            accel: Vector3::new(0.0, GRAVITY, 0.0),
+            accel: Vector3::new(0.0, DEFAULT_GRAVITY, 0.0),
             mass: 1.0,
+            inverse_inertia: Matrix3::identity(),
             angular_position: Quaternion::one(),
             angular_velocity: Vector3::zero(),
             angular_accel: Vector3::zero(),
@@ -29,9 +41,43 @@ impl Physics {
     pub fn apply_force(&mut self, force: Vector3<f32>) {
         self.accel += force / self.mass;
     }
-    // Returns the delta in position.
-    pub fn update(&mut self, delta_t: f32, max_vel: f32) -> Vector3<f32> {
-        let old_pos = self.position;
+    // Torque in world space. Like forces, torques add up until angular_accel is reset.
+    pub fn apply_torque(&mut self, torque: Vector3<f32>) {
+        self.angular_accel += self.world_inverse_inertia() * torque;
+    }
+    // An instant change in momentum, applied at `offset` from the center of mass. Off-center
+    // impulses spin the body as well as moving it.
+    pub fn apply_impulse(&mut self, impulse: Vector3<f32>, offset: Vector3<f32>) {
+        self.velocity += impulse / self.mass;
+        self.angular_velocity += self.world_inverse_inertia() * offset.cross(impulse);
+    }
+    // The inverse inertia tensor as it is with the body turned the way it is now.
+    pub fn world_inverse_inertia(&self) -> Matrix3<f32> {
+        let rotation = Matrix3::from(self.angular_position);
+        rotation * self.inverse_inertia * rotation.transpose()
+    }
+    // The velocity of the point at `offset` from the center of mass.
+    pub fn velocity_at(&self, offset: Vector3<f32>) -> Vector3<f32> {
+        self.velocity + self.angular_velocity.cross(offset)
+    }
+    // Rigid bodies step their velocities and positions separately, with contacts solved in
+    // between: semi-implicit Euler, which keeps resting contacts from creeping.
+    pub fn integrate_velocities(&mut self, delta_t: f32, max_vel: f32) {
+        self.velocity += self.accel * delta_t;
+        if self.velocity.magnitude() > max_vel {
+            self.velocity = self.velocity.normalize_to(max_vel);
+        }
+        self.angular_velocity += self.angular_accel * delta_t;
+    }
+    pub fn integrate_positions(&mut self, delta_t: f32) {
+        self.position += self.velocity * delta_t;
+        // dq/dt = w q / 2, with w as a pure quaternion.
+        let spin = Quaternion::from_sv(0.0, self.angular_velocity) * self.angular_position;
+        self.angular_position = (self.angular_position + spin * (0.5 * delta_t)).normalize();
+    }
+    // Speeds up by accel over delta_t and returns how far that takes the body, without moving it,
+    // for whoever moves it to check the way is clear first.
+    pub fn displacement(&mut self, delta_t: f32, max_vel: f32) -> Vector3<f32> {
         let delta_v = self.accel * delta_t;
         let delta_pos = delta_t * (self.velocity + 0.5 * delta_v);
         self.velocity += delta_v;
@@ -39,49 +85,112 @@ impl Physics {
         // TODO: this affects vertical velocity.
         if max_vel < 0.0 || self.velocity.magnitude() > max_vel {
             self.velocity = self.velocity.normalize_to(max_vel);
-            self.position += self.velocity * delta_t;
+            self.velocity * delta_t
         } else {
-            self.position += delta_pos;
+            delta_pos
         }
-        self.position - old_pos
+    }
+    // The collision shape where the body is now.
+    pub fn hull(&self) -> Hull<'_> {
+        Hull { points: &self.collision.vertices, offset: self.position.to_vec() }
     }
 }
 
-#[derive(Clone)]
+// How a model's instances collide.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColliderKind {
+    // The convex hull of the vertices. Fast, and right for anything without dents or holes.
+    Hull,
+    // Every triangle on its own, for shapes a hull would fill in.
+    Mesh,
+    // Nothing collides with it.
+    None,
+}
+impl ColliderKind {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "hull" => Some(ColliderKind::Hull),
+            "mesh" => Some(ColliderKind::Mesh),
+            "none" => Some(ColliderKind::None),
+            _ => None,
+        }
+    }
+}
+
+// A collision shape in model space. Instances place it with their position, scale and rotation.
+#[derive(Clone, Debug)]
 pub struct Collision {
+    // Without duplicates, which meshes have wherever a corner has more than one normal or texture
+    // coordinate.
     pub vertices: Vec<Vector3<f32>>,
+    // Three indices into vertices per triangle.
+    pub indices: Vec<u32>,
+    pub bounds: Aabb,
+    // The bounding sphere is centered on the bounds.
+    pub radius: f32,
+    pub kind: ColliderKind,
 }
 impl Collision {
-    pub fn new(vertices: Vec<Vector3<f32>>, _indices: Vec<u32>) -> Self {
-        Collision { vertices }
+    // A convex hull collider. Set kind to collide with the triangles instead.
+    pub fn new(vertices: Vec<Vector3<f32>>, indices: Vec<u32>) -> Self {
+        let mut unique: Vec<Vector3<f32>> = vec![];
+        let mut remap: HashMap<[u32; 3], u32> = HashMap::new();
+        let indices_of_unique: Vec<u32> = vertices
+            .iter()
+            .map(|vertex| {
+                let bits = [vertex.x, vertex.y, vertex.z].map(f32::to_bits);
+                *remap.entry(bits).or_insert_with(|| {
+                    unique.push(*vertex);
+                    unique.len() as u32 - 1
+                })
+            })
+            .collect();
+        let indices = indices.iter().map(|&index| indices_of_unique[index as usize]).collect();
+        let bounds = Aabb::from_points(unique.iter().copied());
+        let center = (bounds.min + bounds.max) / 2.0;
+        let radius = unique.iter().map(|vertex| (vertex - center).magnitude()).fold(0.0, f32::max);
+        Collision { vertices: unique, indices, bounds, radius, kind: ColliderKind::Hull }
     }
-    #[allow(unused)]
-    fn calculate_bounding_box(vertices: &Vec<Vector3<f32>>) -> (Vector3<f32>, Vector3<f32>) {
-        if vertices.is_empty() {
-            return (Vector3::zero(), Vector3::zero());
-        }
-        let mut max: Vector3<f32> = vertices[0];
-        let mut min: Vector3<f32> = vertices[0];
-        for vertex in vertices {
-            if vertex.x > max.x {
-                max.x = vertex.x;
-            }
-            if vertex.y > max.y {
-                max.x = vertex.y;
-            }
-            if vertex.z > max.z {
-                max.x = vertex.z;
-            }
-            if vertex.x > min.x {
-                min.x = vertex.x;
-            }
-            if vertex.y > min.y {
-                min.x = vertex.y;
-            }
-            if vertex.z > min.z {
-                min.x = vertex.z;
-            }
-        }
-        (min, max)
+    // The bounding sphere's center, in model space.
+    pub fn center(&self) -> Vector3<f32> {
+        (self.bounds.min + self.bounds.max) / 2.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shapes_are_measured_from_every_vertex() {
+        // Two corners of a box, each listed twice as meshes do, and the triangle between them.
+        let vertices = [(1.0, -2.0, 3.0), (-1.0, 4.0, -3.0), (1.0, -2.0, 3.0), (-1.0, 4.0, -3.0)]
+            .map(Vector3::from)
+            .to_vec();
+        let collision = Collision::new(vertices, vec![0, 1, 2, 3, 1, 0]);
+        assert_eq!(collision.vertices.len(), 2);
+        assert_eq!(collision.indices, [0, 1, 0, 1, 1, 0]);
+        assert_eq!(collision.bounds.min, Vector3::new(-1.0, -2.0, -3.0));
+        assert_eq!(collision.bounds.max, Vector3::new(1.0, 4.0, 3.0));
+        assert_eq!(collision.center(), Vector3::new(0.0, 1.0, 0.0));
+        assert!((collision.radius - 19.0f32.sqrt()).abs() < 1e-6);
+    }
+
+    #[test]
+    fn torques_spin_bodies_about_their_own_axes() {
+        // Easiest to spin about x and hardest about z, turned a quarter about z so that its y
+        // axis points along world -x.
+        let mut physics = Physics::new();
+        physics.inverse_inertia = Matrix3::from_diagonal(Vector3::new(3.0, 2.0, 1.0));
+        physics.angular_position =
+            Quaternion::from_sv(0.5f32.sqrt(), Vector3::unit_z() * 0.5f32.sqrt());
+        physics.apply_torque(Vector3::unit_x());
+        physics.integrate_velocities(0.5, f32::INFINITY);
+        assert!((physics.angular_velocity - Vector3::unit_x()).magnitude() < 1e-6);
+        // A tenth of a second later, it has turned a tenth of a radian about world x.
+        let before = physics.angular_position;
+        physics.integrate_positions(0.1);
+        let turned = physics.angular_position * before.conjugate();
+        assert!((turned.v - Vector3::unit_x() * 0.05f32.sin()).magnitude() < 1e-3, "{turned:?}");
     }
 }
@@ -0,0 +1,79 @@
+/* Platform layer: window creation, gamepads, and translation of OS events into typed Events. Every
+ * backend produces the same events, so the GPU and game threads do not care which one is running. */
+use crate::events::Key;
+
+use std::time::Duration;
+
+#[cfg(windows)]
+mod win32;
+#[cfg(windows)]
+pub use win32::{begin_timer_period, Closer, SystemGamepad, Window};
+
+#[cfg(target_os = "linux")]
+mod linux;
+#[cfg(target_os = "linux")]
+pub use linux::{begin_timer_period, Closer, SystemGamepad, Window};
+
+// How often to look for a gamepad while none is connected.
+const GAMEPAD_RETRY_INTERVAL: Duration = Duration::from_secs(1);
+// Lets go of the cursor, so that it can be used in other windows. Clicking in the window grabs it
+// again. Like the fullscreen key, this is handled by the backends and still published.
+const RELEASE_CURSOR_KEY: Key = Key::Escape;
+// Switches between a window and fullscreen. The backends handle it themselves, since they own the
+// window, but still publish it like any other key.
+const TOGGLE_FULLSCREEN_KEY: Key = Key::F(11);
+
+// How the window covers the screen. Sizes are always in physical pixels, so that a frame is drawn
+// at the monitor's full resolution whatever its scale factor.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum WindowMode {
+    // A framed window that can be moved and resized, with the configured client size.
+    #[default]
+    Windowed,
+    // A frameless window covering the monitor at the desktop's resolution.
+    Borderless,
+    // Switches the monitor to the configured size and covers it. Falls back to borderless where
+    // the platform won't change the display mode.
+    Fullscreen,
+}
+impl WindowMode {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "windowed" => Some(WindowMode::Windowed),
+            "borderless" => Some(WindowMode::Borderless),
+            "fullscreen" => Some(WindowMode::Fullscreen),
+            _ => None,
+        }
+    }
+    // The mode the fullscreen key switches to from this one. `fullscreen` is the mode to use when
+    // leaving a window, which is whichever one the game started in, or borderless.
+    fn toggled(self, fullscreen: WindowMode) -> WindowMode {
+        match (self, fullscreen) {
+            (WindowMode::Windowed, WindowMode::Windowed) => WindowMode::Borderless,
+            (WindowMode::Windowed, fullscreen) => fullscreen,
+            _ => WindowMode::Windowed,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Rect {
+    pub left: i32,
+    pub top: i32,
+    pub right: i32,
+    pub bottom: i32,
+}
+impl Rect {
+    pub fn width(&self) -> i32 {
+        self.right - self.left
+    }
+    pub fn height(&self) -> i32 {
+        self.bottom - self.top
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Point {
+    pub x: i32,
+    pub y: i32,
+}
@@ -0,0 +1,404 @@
+/* Linux backend, built on winit so that both X11 and Wayland sessions work. winit's events are
+ * translated into the same Events that the Win32 wndproc publishes. Gamepads are read from the
+ * joystick device directly, since winit doesn't handle them. */
+use super::{
+    Point, Rect, WindowMode, GAMEPAD_RETRY_INTERVAL, RELEASE_CURSOR_KEY, TOGGLE_FULLSCREEN_KEY,
+};
+use crate::{
+    events::{Event, EventBus, GamepadAxis, GamepadButton, Key, MouseButton},
+    gamepad::{Gamepad, PadState},
+};
+
+use anyhow::Result;
+use debug_print::debug_println;
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Read},
+    os::unix::fs::OpenOptionsExt,
+    time::Instant,
+};
+use winit::{
+    dpi::PhysicalSize,
+    event::{DeviceEvent, ElementState, WindowEvent},
+    event_loop::{EventLoop, EventLoopProxy},
+    platform::run_on_demand::EventLoopExtRunOnDemand,
+    keyboard::{KeyCode, PhysicalKey},
+    monitor::VideoMode,
+    window::{CursorGrabMode, Fullscreen, WindowBuilder},
+};
+
+pub struct Window {
+    event_loop: EventLoop<()>,
+    window: winit::window::Window,
+    event_bus: EventBus,
+    mode: WindowMode,
+    // Where the fullscreen key goes from a window.
+    fullscreen_mode: WindowMode,
+    size: PhysicalSize<u32>,
+}
+impl Window {
+    // winit windows are DPI aware already; sizes and positions are all in physical pixels.
+    pub fn new(width: i32, height: i32, mode: WindowMode, event_bus: EventBus) -> Result<Self> {
+        let event_loop = EventLoop::new()?;
+        let size = PhysicalSize::new(width as u32, height as u32);
+        let window = WindowBuilder::new()
+            .with_title("My sample window")
+            .with_inner_size(size)
+            .with_decorations(mode == WindowMode::Windowed)
+            .build(&event_loop)?;
+        let mode = set_mode(&window, mode, size);
+        Ok(Window { event_loop, window, event_bus, mode, fullscreen_mode: mode, size })
+    }
+    pub fn client_rect(&self) -> Rect {
+        client_rect(&self.window)
+    }
+    // For other threads to close the window with.
+    pub fn closer(&self) -> Closer {
+        Closer(self.event_loop.create_proxy())
+    }
+    // Pumps the event loop until the window is asked to close, then publishes Quit. The window
+    // stays open until it is dropped, so that whatever draws to it can stop first.
+    pub fn run(&mut self) -> Result<()> {
+        let Window { event_loop, window, event_bus, mode, fullscreen_mode, size } = self;
+        let mut grabbed = grab_cursor(window, true);
+        let result = event_loop.run_on_demand(|event, target| {
+            let event = match event {
+                winit::event::Event::WindowEvent { event, .. } => event,
+                // Sent by a Closer.
+                winit::event::Event::UserEvent(()) => {
+                    target.exit();
+                    return;
+                }
+                // Raw motion, before acceleration, and unaffected by the cursor stopping at the
+                // edge of the window or screen.
+                winit::event::Event::DeviceEvent {
+                    event: DeviceEvent::MouseMotion { delta: (dx, dy) },
+                    ..
+                } if grabbed => {
+                    event_bus.publish(Event::MouseDelta { dx: dx as f32, dy: dy as f32 });
+                    return;
+                }
+                _ => return,
+            };
+            match event {
+                WindowEvent::CloseRequested | WindowEvent::Destroyed => {
+                    debug_println!("Quit");
+                    target.exit();
+                }
+                WindowEvent::Resized(_) => {
+                    debug_println!("Resized");
+                    event_bus.publish(Event::Resized(client_rect(window)));
+                }
+                WindowEvent::Focused(false) => {
+                    grabbed = grab_cursor(window, false);
+                    event_bus.publish(Event::FocusLost);
+                }
+                WindowEvent::CursorMoved { position, .. } => {
+                    let position = Point { x: position.x as i32, y: position.y as i32 };
+                    event_bus.publish(Event::MouseMoved(position));
+                }
+                // The click that takes the cursor back only does that.
+                WindowEvent::MouseInput { state: ElementState::Pressed, .. } if !grabbed => {
+                    grabbed = grab_cursor(window, true);
+                }
+                WindowEvent::MouseInput { state, button, .. } => {
+                    let button = match button {
+                        winit::event::MouseButton::Left => MouseButton::Left,
+                        winit::event::MouseButton::Right => MouseButton::Right,
+                        winit::event::MouseButton::Middle => MouseButton::Middle,
+                        _ => return,
+                    };
+                    event_bus.publish(match state {
+                        ElementState::Pressed => Event::MouseButtonPressed { button },
+                        ElementState::Released => Event::MouseButtonReleased { button },
+                    });
+                }
+                WindowEvent::KeyboardInput { event, .. } => {
+                    let PhysicalKey::Code(code) = event.physical_key else {
+                        return;
+                    };
+                    let Some(key) = to_key(code) else {
+                        return;
+                    };
+                    if key == TOGGLE_FULLSCREEN_KEY
+                        && event.state == ElementState::Pressed
+                        && !event.repeat
+                    {
+                        *mode = set_mode(window, mode.toggled(*fullscreen_mode), *size);
+                    }
+                    if key == RELEASE_CURSOR_KEY && event.state == ElementState::Pressed {
+                        grabbed = grab_cursor(window, false);
+                    }
+                    event_bus.publish(match event.state {
+                        ElementState::Pressed => Event::KeyPressed { key, repeat: event.repeat },
+                        ElementState::Released => Event::KeyReleased { key },
+                    });
+                }
+                _ => (),
+            }
+        });
+        grab_cursor(window, false);
+        event_bus.publish(Event::Quit);
+        Ok(result?)
+    }
+}
+
+#[derive(Clone)]
+pub struct Closer(EventLoopProxy<()>);
+impl Closer {
+    // Makes Window::run return. Does nothing once it has.
+    pub fn close(&self) {
+        let _ = self.0.send_event(());
+    }
+}
+
+// Hides the cursor and keeps it in the window, or gives it back. Returns whether the cursor is now
+// grabbed.
+fn grab_cursor(window: &winit::window::Window, grab: bool) -> bool {
+    // X11 can't lock the cursor in place, but confining it is enough, since motion is read raw.
+    let grabbed = grab
+        && window
+            .set_cursor_grab(CursorGrabMode::Locked)
+            .or_else(|_| window.set_cursor_grab(CursorGrabMode::Confined))
+            .is_ok();
+    if !grabbed {
+        let _ = window.set_cursor_grab(CursorGrabMode::None);
+    }
+    window.set_cursor_visible(!grabbed);
+    grabbed
+}
+
+// Puts the window in `mode`, and returns the mode it ended up in. `size` is the client size for a
+// window, and the display mode for fullscreen.
+fn set_mode(
+    window: &winit::window::Window,
+    mode: WindowMode,
+    size: PhysicalSize<u32>,
+) -> WindowMode {
+    match mode {
+        WindowMode::Windowed => {
+            window.set_fullscreen(None);
+            window.set_decorations(true);
+            let _ = window.request_inner_size(size);
+        }
+        WindowMode::Borderless => window.set_fullscreen(Some(Fullscreen::Borderless(None))),
+        WindowMode::Fullscreen => match video_mode(window, size) {
+            Some(video_mode) => window.set_fullscreen(Some(Fullscreen::Exclusive(video_mode))),
+            None => {
+                println!(
+                    "The display has no {}x{} mode, using borderless fullscreen instead",
+                    size.width, size.height
+                );
+                return set_mode(window, WindowMode::Borderless, size);
+            }
+        },
+    }
+    mode
+}
+
+// The current monitor's mode at `size` with the highest refresh rate.
+fn video_mode(window: &winit::window::Window, size: PhysicalSize<u32>) -> Option<VideoMode> {
+    window
+        .current_monitor()?
+        .video_modes()
+        .filter(|video_mode| video_mode.size() == size)
+        .max_by_key(|video_mode| (video_mode.refresh_rate_millihertz(), video_mode.bit_depth()))
+}
+
+unsafe impl raw_window_handle::HasRawWindowHandle for Window {
+    fn raw_window_handle(&self) -> raw_window_handle::RawWindowHandle {
+        raw_window_handle::HasRawWindowHandle::raw_window_handle(&self.window)
+    }
+}
+
+unsafe impl raw_window_handle::HasRawDisplayHandle for Window {
+    fn raw_display_handle(&self) -> raw_window_handle::RawDisplayHandle {
+        raw_window_handle::HasRawDisplayHandle::raw_display_handle(&self.window)
+    }
+}
+
+// Reads the first pad through the kernel's joystick interface. The axis and button numbers are the
+// ones the xpad driver uses for Xbox-style pads, which most other pads imitate.
+pub struct SystemGamepad {
+    device: Option<File>,
+    state: PadState,
+    reported: PadState,
+    next_open: Instant,
+}
+impl SystemGamepad {
+    const PATH: &'static str = "/dev/input/js0";
+    pub fn new() -> Self {
+        SystemGamepad {
+            device: None,
+            state: PadState::default(),
+            reported: PadState::default(),
+            next_open: Instant::now(),
+        }
+    }
+    // Applies one js_event: u32 timestamp, i16 value, u8 type, u8 number.
+    fn apply(&mut self, event: [u8; 8]) {
+        const JS_EVENT_BUTTON: u8 = 0x01;
+        const JS_EVENT_AXIS: u8 = 0x02;
+        // Set on the synthetic events that report the initial state after opening.
+        const JS_EVENT_INIT: u8 = 0x80;
+        let value = i16::from_ne_bytes([event[4], event[5]]);
+        let number = event[7];
+        let stick = |value: i16| (value as f32 / i16::MAX as f32).clamp(-1.0, 1.0);
+        let trigger = |value: i16| (value as f32 + i16::MAX as f32) / (2.0 * i16::MAX as f32);
+        match event[6] & !JS_EVENT_INIT {
+            JS_EVENT_BUTTON => {
+                let button = match number {
+                    0 => GamepadButton::A,
+                    1 => GamepadButton::B,
+                    2 => GamepadButton::X,
+                    3 => GamepadButton::Y,
+                    4 => GamepadButton::LeftShoulder,
+                    5 => GamepadButton::RightShoulder,
+                    6 => GamepadButton::Back,
+                    7 => GamepadButton::Start,
+                    9 => GamepadButton::LeftThumb,
+                    10 => GamepadButton::RightThumb,
+                    _ => return,
+                };
+                self.set_button(button, value != 0);
+            }
+            JS_EVENT_AXIS => match number {
+                0 => self.state.set_axis(GamepadAxis::LeftX, stick(value)),
+                // The joystick interface has +y pointing down.
+                1 => self.state.set_axis(GamepadAxis::LeftY, -stick(value)),
+                2 => self.state.set_axis(GamepadAxis::LeftTrigger, trigger(value)),
+                3 => self.state.set_axis(GamepadAxis::RightX, stick(value)),
+                4 => self.state.set_axis(GamepadAxis::RightY, -stick(value)),
+                5 => self.state.set_axis(GamepadAxis::RightTrigger, trigger(value)),
+                // The d-pad shows up as a hat, one axis per direction pair.
+                6 => {
+                    self.set_button(GamepadButton::DPadLeft, value < 0);
+                    self.set_button(GamepadButton::DPadRight, value > 0);
+                }
+                7 => {
+                    self.set_button(GamepadButton::DPadUp, value < 0);
+                    self.set_button(GamepadButton::DPadDown, value > 0);
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+    fn set_button(&mut self, button: GamepadButton, down: bool) {
+        if down {
+            self.state.buttons.insert(button);
+        } else {
+            self.state.buttons.remove(&button);
+        }
+    }
+}
+impl Gamepad for SystemGamepad {
+    fn poll(&mut self, events: &mut Vec<Event>) {
+        // std doesn't export this without the libc crate. It's the same on x86 and ARM.
+        const O_NONBLOCK: i32 = 0o4000;
+        if self.device.is_none() && Instant::now() >= self.next_open {
+            self.next_open = Instant::now() + GAMEPAD_RETRY_INTERVAL;
+            self.device =
+                OpenOptions::new().read(true).custom_flags(O_NONBLOCK).open(Self::PATH).ok();
+            if self.device.is_some() {
+                debug_println!("Opened gamepad {}", Self::PATH);
+                self.state = PadState { connected: true, ..PadState::default() };
+            }
+        }
+        while let Some(device) = self.device.as_mut() {
+            let mut event = [0; 8];
+            match device.read_exact(&mut event) {
+                Ok(()) => self.apply(event),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    debug_println!("Lost gamepad {}: {e}", Self::PATH);
+                    self.device = None;
+                    self.state = PadState::default();
+                }
+            }
+        }
+        self.reported.diff(&self.state, events);
+        self.reported.clone_from(&self.state);
+    }
+}
+
+// Linux already sleeps with fine granularity, so there is nothing to adjust.
+#[must_use]
+pub fn begin_timer_period() -> TimerPeriod {
+    TimerPeriod
+}
+
+pub struct TimerPeriod;
+
+fn client_rect(window: &winit::window::Window) -> Rect {
+    let size = window.inner_size();
+    Rect { left: 0, top: 0, right: size.width as i32, bottom: size.height as i32 }
+}
+
+// Maps physical key positions onto the keys they are labeled with on a US layout.
+fn to_key(code: KeyCode) -> Option<Key> {
+    let key = match code {
+        KeyCode::Backspace => Key::Backspace,
+        KeyCode::Tab => Key::Tab,
+        KeyCode::Enter => Key::Enter,
+        KeyCode::ShiftLeft | KeyCode::ShiftRight => Key::Shift,
+        KeyCode::ControlLeft | KeyCode::ControlRight => Key::Control,
+        KeyCode::AltLeft | KeyCode::AltRight => Key::Alt,
+        KeyCode::Escape => Key::Escape,
+        KeyCode::Space => Key::Space,
+        KeyCode::ArrowLeft => Key::Left,
+        KeyCode::ArrowUp => Key::Up,
+        KeyCode::ArrowRight => Key::Right,
+        KeyCode::ArrowDown => Key::Down,
+        KeyCode::Digit0 => Key::Digit(0),
+        KeyCode::Digit1 => Key::Digit(1),
+        KeyCode::Digit2 => Key::Digit(2),
+        KeyCode::Digit3 => Key::Digit(3),
+        KeyCode::Digit4 => Key::Digit(4),
+        KeyCode::Digit5 => Key::Digit(5),
+        KeyCode::Digit6 => Key::Digit(6),
+        KeyCode::Digit7 => Key::Digit(7),
+        KeyCode::Digit8 => Key::Digit(8),
+        KeyCode::Digit9 => Key::Digit(9),
+        KeyCode::KeyA => Key::Letter('A'),
+        KeyCode::KeyB => Key::Letter('B'),
+        KeyCode::KeyC => Key::Letter('C'),
+        KeyCode::KeyD => Key::Letter('D'),
+        KeyCode::KeyE => Key::Letter('E'),
+        KeyCode::KeyF => Key::Letter('F'),
+        KeyCode::KeyG => Key::Letter('G'),
+        KeyCode::KeyH => Key::Letter('H'),
+        KeyCode::KeyI => Key::Letter('I'),
+        KeyCode::KeyJ => Key::Letter('J'),
+        KeyCode::KeyK => Key::Letter('K'),
+        KeyCode::KeyL => Key::Letter('L'),
+        KeyCode::KeyM => Key::Letter('M'),
+        KeyCode::KeyN => Key::Letter('N'),
+        KeyCode::KeyO => Key::Letter('O'),
+        KeyCode::KeyP => Key::Letter('P'),
+        KeyCode::KeyQ => Key::Letter('Q'),
+        KeyCode::KeyR => Key::Letter('R'),
+        KeyCode::KeyS => Key::Letter('S'),
+        KeyCode::KeyT => Key::Letter('T'),
+        KeyCode::KeyU => Key::Letter('U'),
+        KeyCode::KeyV => Key::Letter('V'),
+        KeyCode::KeyW => Key::Letter('W'),
+        KeyCode::KeyX => Key::Letter('X'),
+        KeyCode::KeyY => Key::Letter('Y'),
+        KeyCode::KeyZ => Key::Letter('Z'),
+        KeyCode::F1 => Key::F(1),
+        KeyCode::F2 => Key::F(2),
+        KeyCode::F3 => Key::F(3),
+        KeyCode::F4 => Key::F(4),
+        KeyCode::F5 => Key::F(5),
+        KeyCode::F6 => Key::F(6),
+        KeyCode::F7 => Key::F(7),
+        KeyCode::F8 => Key::F(8),
+        KeyCode::F9 => Key::F(9),
+        KeyCode::F10 => Key::F(10),
+        KeyCode::F11 => Key::F(11),
+        KeyCode::F12 => Key::F(12),
+        _ => return None,
+    };
+    Some(key)
+}
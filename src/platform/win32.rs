@@ -0,0 +1,552 @@
+/* Win32 backend: a window whose wndproc publishes Events to the event bus, and gamepads through
+ * XInput. The process is per-monitor DPI aware, so Windows never scales the frames it draws. */
+use super::{
+    Point, Rect, WindowMode, GAMEPAD_RETRY_INTERVAL, RELEASE_CURSOR_KEY, TOGGLE_FULLSCREEN_KEY,
+};
+use crate::{
+    events::{Event, EventBus, GamepadAxis, GamepadButton, Key, MouseButton},
+    gamepad::{Gamepad, PadState},
+};
+
+use anyhow::Result;
+use debug_print::debug_println;
+use std::{
+    cell::{Cell, RefCell},
+    ffi::c_void,
+    mem::{self},
+    time::Instant,
+};
+use windows::{
+    core::*,
+    Win32::{
+        Foundation::{ERROR_SUCCESS, HINSTANCE, HWND, LPARAM, LRESULT, POINT, RECT, WPARAM},
+        Graphics::Gdi::{
+            ChangeDisplaySettingsW, ClientToScreen, GetMonitorInfoW, MonitorFromWindow,
+            ValidateRect, CDS_FULLSCREEN, CDS_TYPE, DEVMODEW, DISP_CHANGE_SUCCESSFUL,
+            DM_PELSHEIGHT, DM_PELSWIDTH, MONITORINFO, MONITOR_DEFAULTTONEAREST,
+            MONITOR_DEFAULTTOPRIMARY,
+        },
+        System::LibraryLoader::GetModuleHandleA,
+        UI::{
+            HiDpi::{SetProcessDpiAwarenessContext, DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2},
+            Input::{
+                GetRawInputData, KeyboardAndMouse::*, RegisterRawInputDevices, XboxController::*,
+                HRAWINPUT, RAWINPUT, RAWINPUTDEVICE, RAWINPUTDEVICE_FLAGS, RAWINPUTHEADER,
+                RID_INPUT, RIM_TYPEMOUSE,
+            },
+            WindowsAndMessaging::*,
+        },
+    },
+};
+
+const WINDOWED_STYLE: WINDOW_STYLE = WS_OVERLAPPEDWINDOW;
+const FULLSCREEN_STYLE: WINDOW_STYLE = WS_POPUP;
+
+thread_local! {
+    // wndproc always runs on the thread that created the window, so it finds the bus here.
+    static EVENT_BUS: RefCell<Option<EventBus>> = RefCell::new(None);
+    static DISPLAY: Cell<Display> = Cell::new(Display::default());
+    // Whether the cursor is hidden and clipped to the window, and mouse motion is published.
+    static GRABBED: Cell<bool> = Cell::new(false);
+}
+
+// What wndproc needs to switch window modes.
+#[derive(Clone, Copy, Default)]
+struct Display {
+    mode: WindowMode,
+    // Where the fullscreen key goes from a window.
+    fullscreen_mode: WindowMode,
+    // The display mode for exclusive fullscreen.
+    width: i32,
+    height: i32,
+    // Where the window was before it left windowed mode, to put it back.
+    windowed_rect: RECT,
+}
+
+fn publish(event: Event) {
+    EVENT_BUS.with(|bus| {
+        if let Some(bus) = bus.borrow_mut().as_mut() {
+            bus.publish(event);
+        }
+    });
+}
+
+pub struct Window {
+    hwnd: HWND,
+    hinstance: HINSTANCE,
+}
+impl Window {
+    // width and height are the client size in windowed mode, and the display mode in fullscreen.
+    pub fn new(width: i32, height: i32, mode: WindowMode, event_bus: EventBus) -> Result<Self> {
+        // Installed first, so that the messages sent while the window is created are published.
+        EVENT_BUS.with(|bus| *bus.borrow_mut() = Some(event_bus));
+        // Fails if a manifest already set it, which is fine.
+        let _ =
+            unsafe { SetProcessDpiAwarenessContext(DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2) };
+        let hinstance: HINSTANCE = unsafe { GetModuleHandleA(None) }?.into();
+        let window_class_name = s!("window");
+        let wc = WNDCLASSA {
+            hCursor: unsafe { LoadCursorW(None, IDC_ARROW) }?,
+            hInstance: hinstance,
+            lpszClassName: window_class_name,
+            style: CS_HREDRAW | CS_VREDRAW,
+            lpfnWndProc: Some(wndproc),
+            ..Default::default()
+        };
+        let atom = unsafe { RegisterClassA(&wc) };
+        debug_assert!(atom != 0);
+
+        // Created hidden as a window of the requested client size, which is where it goes back to
+        // when leaving fullscreen.
+        let mut frame = RECT { left: 0, top: 0, right: width, bottom: height };
+        unsafe {
+            AdjustWindowRectEx(&mut frame, WINDOWED_STYLE, false, WINDOW_EX_STYLE::default())
+        }?;
+        let hwnd = unsafe {
+            CreateWindowExA(
+                WINDOW_EX_STYLE::default(),
+                window_class_name,
+                s!("My sample window"),
+                WINDOWED_STYLE,
+                CW_USEDEFAULT,
+                CW_USEDEFAULT,
+                frame.right - frame.left,
+                frame.bottom - frame.top,
+                None,
+                None,
+                hinstance,
+                None,
+            )
+        };
+        let display = Display { width, height, ..Default::default() };
+        DISPLAY.with(|cell| cell.set(display));
+        let mode = set_mode(hwnd, mode);
+        DISPLAY.with(|cell| cell.set(Display { fullscreen_mode: mode, ..cell.get() }));
+        // Mouse motion comes from raw input, which isn't clamped to the screen or accelerated.
+        let mouse = RAWINPUTDEVICE {
+            usUsagePage: 0x01, // Generic desktop controls
+            usUsage: 0x02,     // Mouse
+            dwFlags: RAWINPUTDEVICE_FLAGS(0),
+            hwndTarget: hwnd,
+        };
+        unsafe { RegisterRawInputDevices(&[mouse], mem::size_of::<RAWINPUTDEVICE>() as u32) }?;
+        grab_cursor(hwnd, true);
+        Ok(Window { hwnd, hinstance })
+    }
+    pub fn client_rect(&self) -> Rect {
+        client_rect(self.hwnd)
+    }
+    // For other threads to close the window with.
+    pub fn closer(&self) -> Closer {
+        Closer(self.hwnd)
+    }
+    // Pumps the message loop until the window is asked to close, then publishes Quit. The window
+    // stays open until it is dropped, so that whatever draws to it can stop first.
+    pub fn run(&mut self) -> Result<()> {
+        let mut message = MSG::default();
+        unsafe {
+            while GetMessageA(&mut message, None, 0, 0).into() {
+                DispatchMessageA(&message);
+            }
+        }
+        grab_cursor(self.hwnd, false);
+        publish(Event::Quit);
+        Ok(())
+    }
+}
+impl Drop for Window {
+    fn drop(&mut self) {
+        let _ = unsafe { DestroyWindow(self.hwnd) };
+    }
+}
+
+#[derive(Clone)]
+pub struct Closer(HWND);
+impl Closer {
+    // Makes Window::run return. Does nothing once it has.
+    pub fn close(&self) {
+        let _ = unsafe { PostMessageA(self.0, WM_CLOSE, WPARAM(0), LPARAM(0)) };
+    }
+}
+
+unsafe impl raw_window_handle::HasRawWindowHandle for Window {
+    fn raw_window_handle(&self) -> raw_window_handle::RawWindowHandle {
+        let mut h = raw_window_handle::Win32WindowHandle::empty();
+        h.hwnd = self.hwnd.0 as *mut c_void;
+        h.hinstance = self.hinstance.0 as *mut c_void;
+        raw_window_handle::RawWindowHandle::Win32(h)
+    }
+}
+
+unsafe impl raw_window_handle::HasRawDisplayHandle for Window {
+    fn raw_display_handle(&self) -> raw_window_handle::RawDisplayHandle {
+        raw_window_handle::RawDisplayHandle::from(raw_window_handle::WindowsDisplayHandle::empty())
+    }
+}
+
+// Reads the first XInput pad. Polling a pad that isn't there is slow, so while none is connected
+// it only checks again every GAMEPAD_RETRY_INTERVAL.
+pub struct SystemGamepad {
+    state: PadState,
+    reported: PadState,
+    next_check: Instant,
+}
+impl SystemGamepad {
+    pub fn new() -> Self {
+        SystemGamepad {
+            state: PadState::default(),
+            reported: PadState::default(),
+            next_check: Instant::now(),
+        }
+    }
+}
+impl Gamepad for SystemGamepad {
+    fn poll(&mut self, events: &mut Vec<Event>) {
+        const BUTTONS: [(XINPUT_GAMEPAD_BUTTON_FLAGS, GamepadButton); 14] = [
+            (XINPUT_GAMEPAD_A, GamepadButton::A),
+            (XINPUT_GAMEPAD_B, GamepadButton::B),
+            (XINPUT_GAMEPAD_X, GamepadButton::X),
+            (XINPUT_GAMEPAD_Y, GamepadButton::Y),
+            (XINPUT_GAMEPAD_LEFT_SHOULDER, GamepadButton::LeftShoulder),
+            (XINPUT_GAMEPAD_RIGHT_SHOULDER, GamepadButton::RightShoulder),
+            (XINPUT_GAMEPAD_LEFT_THUMB, GamepadButton::LeftThumb),
+            (XINPUT_GAMEPAD_RIGHT_THUMB, GamepadButton::RightThumb),
+            (XINPUT_GAMEPAD_START, GamepadButton::Start),
+            (XINPUT_GAMEPAD_BACK, GamepadButton::Back),
+            (XINPUT_GAMEPAD_DPAD_UP, GamepadButton::DPadUp),
+            (XINPUT_GAMEPAD_DPAD_DOWN, GamepadButton::DPadDown),
+            (XINPUT_GAMEPAD_DPAD_LEFT, GamepadButton::DPadLeft),
+            (XINPUT_GAMEPAD_DPAD_RIGHT, GamepadButton::DPadRight),
+        ];
+        if !self.state.connected && Instant::now() < self.next_check {
+            return;
+        }
+        let mut xinput: XINPUT_STATE = unsafe { mem::zeroed() };
+        if unsafe { XInputGetState(0, &mut xinput) } != ERROR_SUCCESS.0 {
+            self.next_check = Instant::now() + GAMEPAD_RETRY_INTERVAL;
+            self.state = PadState::default();
+        } else {
+            let pad = xinput.Gamepad;
+            let stick = |value: i16| (value as f32 / i16::MAX as f32).clamp(-1.0, 1.0);
+            let trigger = |value: u8| value as f32 / u8::MAX as f32;
+            self.state.connected = true;
+            self.state.buttons.clear();
+            for (flag, button) in BUTTONS {
+                if pad.wButtons.contains(flag) {
+                    self.state.buttons.insert(button);
+                }
+            }
+            self.state.set_axis(GamepadAxis::LeftX, stick(pad.sThumbLX));
+            self.state.set_axis(GamepadAxis::LeftY, stick(pad.sThumbLY));
+            self.state.set_axis(GamepadAxis::RightX, stick(pad.sThumbRX));
+            self.state.set_axis(GamepadAxis::RightY, stick(pad.sThumbRY));
+            self.state.set_axis(GamepadAxis::LeftTrigger, trigger(pad.bLeftTrigger));
+            self.state.set_axis(GamepadAxis::RightTrigger, trigger(pad.bRightTrigger));
+        }
+        self.reported.diff(&self.state, events);
+        self.reported.clone_from(&self.state);
+    }
+}
+
+// Sets sleep granularity to 1ms, until the returned TimerPeriod is dropped.
+#[must_use]
+pub fn begin_timer_period() -> TimerPeriod {
+    unsafe { windows::Win32::Media::timeBeginPeriod(1) };
+    TimerPeriod
+}
+
+pub struct TimerPeriod;
+impl Drop for TimerPeriod {
+    fn drop(&mut self) {
+        unsafe { windows::Win32::Media::timeEndPeriod(1) };
+    }
+}
+
+// Moves the window into `mode`, and returns the mode it ended up in: borderless if the display
+// mode can't be changed.
+fn set_mode(window: HWND, mode: WindowMode) -> WindowMode {
+    let mut display = DISPLAY.with(|cell| cell.get());
+    if display.mode == WindowMode::Windowed {
+        let _ = unsafe { GetWindowRect(window, &mut display.windowed_rect) };
+    }
+    if display.mode == WindowMode::Fullscreen && mode != WindowMode::Fullscreen {
+        restore_resolution();
+    }
+    let mode = match mode {
+        WindowMode::Fullscreen if !change_resolution(display.width, display.height) => {
+            println!(
+                "The display has no {}x{} mode, using borderless fullscreen instead",
+                display.width, display.height
+            );
+            WindowMode::Borderless
+        }
+        mode => mode,
+    };
+    let (style, rect) = match mode {
+        WindowMode::Windowed => (WINDOWED_STYLE, display.windowed_rect),
+        WindowMode::Borderless => (FULLSCREEN_STYLE, monitor_rect(window, false)),
+        // The resolution only changed on the primary monitor.
+        WindowMode::Fullscreen => (FULLSCREEN_STYLE, monitor_rect(window, true)),
+    };
+    display.mode = mode;
+    // Stored before moving the window, since that calls back into wndproc.
+    DISPLAY.with(|cell| cell.set(display));
+    unsafe { SetWindowLongPtrA(window, GWL_STYLE, style.0 as isize) };
+    place(window, rect, SWP_FRAMECHANGED | SWP_SHOWWINDOW);
+    mode
+}
+
+// Moves and sizes the window to cover `rect`. Sends WM_SIZE, which publishes the new client size.
+fn place(window: HWND, rect: RECT, flags: SET_WINDOW_POS_FLAGS) {
+    let (width, height) = (rect.right - rect.left, rect.bottom - rect.top);
+    let _ = unsafe { SetWindowPos(window, HWND_TOP, rect.left, rect.top, width, height, flags) };
+}
+
+// Switches the primary monitor to width x height until restore_resolution, returning whether it
+// worked.
+fn change_resolution(width: i32, height: i32) -> bool {
+    let devmode = DEVMODEW {
+        dmSize: mem::size_of::<DEVMODEW>() as u16,
+        dmFields: DM_PELSWIDTH | DM_PELSHEIGHT,
+        dmPelsWidth: width as u32,
+        dmPelsHeight: height as u32,
+        ..Default::default()
+    };
+    unsafe { ChangeDisplaySettingsW(Some(&devmode), CDS_FULLSCREEN) == DISP_CHANGE_SUCCESSFUL }
+}
+
+fn restore_resolution() {
+    unsafe { ChangeDisplaySettingsW(None, CDS_TYPE(0)) };
+}
+
+// The bounds of the monitor the window is mostly on, or of the primary monitor.
+fn monitor_rect(window: HWND, primary: bool) -> RECT {
+    let flags = if primary { MONITOR_DEFAULTTOPRIMARY } else { MONITOR_DEFAULTTONEAREST };
+    let mut info =
+        MONITORINFO { cbSize: mem::size_of::<MONITORINFO>() as u32, ..Default::default() };
+    let _ = unsafe { GetMonitorInfoW(MonitorFromWindow(window, flags), &mut info) };
+    info.rcMonitor
+}
+
+fn client_rect(window: HWND) -> Rect {
+    let mut rect: RECT = unsafe { mem::zeroed() };
+    let _ = unsafe { GetClientRect(window, &mut rect) };
+    Rect { left: rect.left, top: rect.top, right: rect.right, bottom: rect.bottom }
+}
+
+// Maps virtual key codes onto the keys they are labeled with on a US layout.
+fn to_key(virtual_key: u16) -> Option<Key> {
+    let key = match VIRTUAL_KEY(virtual_key) {
+        VK_BACK => Key::Backspace,
+        VK_TAB => Key::Tab,
+        VK_RETURN => Key::Enter,
+        VK_SHIFT => Key::Shift,
+        VK_CONTROL => Key::Control,
+        VK_MENU => Key::Alt,
+        VK_ESCAPE => Key::Escape,
+        VK_SPACE => Key::Space,
+        VK_LEFT => Key::Left,
+        VK_UP => Key::Up,
+        VK_RIGHT => Key::Right,
+        VK_DOWN => Key::Down,
+        // Digits and letters use their ASCII codes.
+        VIRTUAL_KEY(0x30..=0x39) => Key::Digit((virtual_key - 0x30) as u8),
+        VIRTUAL_KEY(0x41..=0x5A) => Key::Letter(virtual_key as u8 as char),
+        VIRTUAL_KEY(code) if (VK_F1.0..=VK_F12.0).contains(&code) => {
+            Key::F((code - VK_F1.0 + 1) as u8)
+        }
+        _ => return None,
+    };
+    Some(key)
+}
+
+// Hides the cursor and clips it to the client area, or gives it back.
+fn grab_cursor(window: HWND, grab: bool) {
+    GRABBED.with(|cell| cell.set(grab));
+    if grab {
+        clip_cursor(window);
+        unsafe { SetCursor(None) };
+    } else {
+        let _ = unsafe { ClipCursor(None) };
+        unsafe { SetCursor(LoadCursorW(None, IDC_ARROW).unwrap_or_default()) };
+    }
+}
+
+// Clips the cursor to the client area as it is now. Needs redoing whenever the window moves.
+fn clip_cursor(window: HWND) {
+    let rect = client_rect(window);
+    let mut corners =
+        [POINT { x: rect.left, y: rect.top }, POINT { x: rect.right, y: rect.bottom }];
+    for corner in &mut corners {
+        unsafe { ClientToScreen(window, corner) };
+    }
+    let [top_left, bottom_right] = corners;
+    let clip =
+        RECT { left: top_left.x, top: top_left.y, right: bottom_right.x, bottom: bottom_right.y };
+    let _ = unsafe { ClipCursor(Some(&clip)) };
+}
+
+fn is_grabbed() -> bool {
+    GRABBED.with(|cell| cell.get())
+}
+
+fn mouse_button(window: HWND, event: Event) -> LRESULT {
+    match event {
+        // The click that takes the cursor back only does that.
+        Event::MouseButtonPressed { .. } if !is_grabbed() => grab_cursor(window, true),
+        event => publish(event),
+    }
+    LRESULT(0)
+}
+
+// The relative motion in a WM_INPUT message, if it is from a mouse.
+fn raw_mouse_motion(lparam: LPARAM) -> Option<(i32, i32)> {
+    // Tablets and remote desktop sessions report absolute positions instead.
+    const MOUSE_MOVE_ABSOLUTE: u16 = 0x01;
+    let mut raw: RAWINPUT = unsafe { mem::zeroed() };
+    let mut size = mem::size_of::<RAWINPUT>() as u32;
+    let read = unsafe {
+        GetRawInputData(
+            HRAWINPUT(lparam.0),
+            RID_INPUT,
+            Some(&mut raw as *mut RAWINPUT as *mut c_void),
+            &mut size,
+            mem::size_of::<RAWINPUTHEADER>() as u32,
+        )
+    };
+    if read == u32::MAX || raw.header.dwType != RIM_TYPEMOUSE.0 {
+        return None;
+    }
+    let mouse = unsafe { raw.data.mouse };
+    (mouse.usFlags & MOUSE_MOVE_ABSOLUTE == 0).then_some((mouse.lLastX, mouse.lLastY))
+}
+
+extern "system" fn wndproc(window: HWND, message: u32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+    match message {
+        WM_PAINT => {
+            unsafe { ValidateRect(window, None) };
+            LRESULT(0)
+        }
+        // Ends Window::run, which leaves destroying the window to Window's drop.
+        WM_CLOSE => {
+            debug_println!("WM_CLOSE");
+            unsafe { PostQuitMessage(0) };
+            LRESULT(0)
+        }
+        WM_DESTROY => {
+            debug_println!("WM_DESTROY");
+            if DISPLAY.with(|cell| cell.get()).mode == WindowMode::Fullscreen {
+                restore_resolution();
+            }
+            LRESULT(0)
+        }
+        WM_SIZE => {
+            debug_println!("WM_SIZE");
+            if is_grabbed() {
+                clip_cursor(window);
+            }
+            publish(Event::Resized(client_rect(window)));
+            LRESULT(0)
+        }
+        WM_MOVE => {
+            if is_grabbed() {
+                clip_cursor(window);
+            }
+            LRESULT(0)
+        }
+        WM_ACTIVATEAPP => {
+            // Exclusive fullscreen gives the desktop its resolution back while switched away.
+            let display = DISPLAY.with(|cell| cell.get());
+            if display.mode == WindowMode::Fullscreen {
+                if wparam.0 == 0 {
+                    restore_resolution();
+                    unsafe { ShowWindow(window, SW_MINIMIZE) };
+                } else if change_resolution(display.width, display.height) {
+                    place(window, monitor_rect(window, true), SWP_SHOWWINDOW);
+                }
+            }
+            LRESULT(0)
+        }
+        WM_DPICHANGED => {
+            // lparam is the size Windows suggests for the new scale. Fullscreen windows already
+            // cover the monitor.
+            if DISPLAY.with(|cell| cell.get()).mode == WindowMode::Windowed {
+                let rect = unsafe { *(lparam.0 as *const RECT) };
+                place(window, rect, SWP_NOZORDER | SWP_NOACTIVATE);
+            }
+            LRESULT(0)
+        }
+        WM_KILLFOCUS => {
+            grab_cursor(window, false);
+            publish(Event::FocusLost);
+            LRESULT(0)
+        }
+        WM_MOUSEACTIVATE => {
+            debug_println!("WM_MOUSEACTIVATE");
+            LRESULT(0)
+        }
+        WM_MOUSEMOVE => {
+            // The low and high words are the signed client coordinates.
+            let x = lparam.0 as u16 as i16 as i32;
+            let y = (lparam.0 >> 16) as u16 as i16 as i32;
+            publish(Event::MouseMoved(Point { x, y }));
+            LRESULT(0)
+        }
+        WM_INPUT => {
+            if let Some((dx, dy)) = raw_mouse_motion(lparam).filter(|_| is_grabbed()) {
+                publish(Event::MouseDelta { dx: dx as f32, dy: dy as f32 });
+            }
+            // Lets Windows clean up after the message.
+            unsafe { DefWindowProcA(window, message, wparam, lparam) }
+        }
+        WM_LBUTTONDOWN => {
+            mouse_button(window, Event::MouseButtonPressed { button: MouseButton::Left })
+        }
+        WM_LBUTTONUP => {
+            mouse_button(window, Event::MouseButtonReleased { button: MouseButton::Left })
+        }
+        WM_RBUTTONDOWN => {
+            mouse_button(window, Event::MouseButtonPressed { button: MouseButton::Right })
+        }
+        WM_RBUTTONUP => {
+            mouse_button(window, Event::MouseButtonReleased { button: MouseButton::Right })
+        }
+        WM_MBUTTONDOWN => {
+            mouse_button(window, Event::MouseButtonPressed { button: MouseButton::Middle })
+        }
+        WM_MBUTTONUP => {
+            mouse_button(window, Event::MouseButtonReleased { button: MouseButton::Middle })
+        }
+        WM_KEYDOWN | WM_SYSKEYDOWN | WM_KEYUP | WM_SYSKEYUP => {
+            if let Some(key) = to_key(wparam.0 as u16) {
+                let pressed = message == WM_KEYDOWN || message == WM_SYSKEYDOWN;
+                // Bit 30 is the previous key state, which is only set for auto-repeat.
+                let repeat = lparam.0 & (1 << 30) != 0;
+                if key == TOGGLE_FULLSCREEN_KEY && pressed && !repeat {
+                    let display = DISPLAY.with(|cell| cell.get());
+                    set_mode(window, display.mode.toggled(display.fullscreen_mode));
+                }
+                if key == RELEASE_CURSOR_KEY && pressed {
+                    grab_cursor(window, false);
+                }
+                publish(if pressed {
+                    Event::KeyPressed { key, repeat }
+                } else {
+                    Event::KeyReleased { key }
+                });
+            }
+            if message == WM_SYSKEYDOWN || message == WM_SYSKEYUP {
+                // Let Windows handle Alt+F4 and friends.
+                unsafe { DefWindowProcA(window, message, wparam, lparam) }
+            } else {
+                LRESULT(0)
+            }
+        }
+        // Hidden while grabbed, and otherwise whatever Windows picks for where it is.
+        WM_SETCURSOR if is_grabbed() => unsafe {
+            SetCursor(None);
+            LRESULT(1)
+        },
+        _ => unsafe { DefWindowProcA(window, message, wparam, lparam) },
+    }
+}
@@ -0,0 +1,58 @@
+/* ModelRegistry: the models a scene uses, each identified by a ModelHandle. Game code refers to
+ * models only by handle (or looks one up by name), and the renderer keeps its GPU data keyed by
+ * the same handles, so the two sides can't disagree about which model is which. Each model's
+ * geometry is loaded once and kept here, for the renderer to upload, along with the collision
+ * shape built from it. */
+use crate::{model::Geometry, physics::Collision};
+
+use std::collections::HashMap;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ModelAsset {
+    Obj(String),
+    Cube,
+    DoubleCube,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ModelHandle(u32);
+
+#[derive(Clone, Default)]
+pub struct ModelRegistry {
+    handles: HashMap<String, ModelHandle>,
+    // Indexed by handle, in registration order.
+    models: Vec<(Geometry, Collision)>,
+}
+impl ModelRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    // Returns None if a model with this name is already registered.
+    pub fn register(
+        &mut self,
+        name: &str,
+        geometry: Geometry,
+        collision: Collision,
+    ) -> Option<ModelHandle> {
+        if self.handles.contains_key(name) {
+            return None;
+        }
+        let handle = ModelHandle(self.models.len() as u32);
+        self.handles.insert(name.to_string(), handle);
+        self.models.push((geometry, collision));
+        Some(handle)
+    }
+    pub fn handle(&self, name: &str) -> Option<ModelHandle> {
+        self.handles.get(name).copied()
+    }
+    pub fn geometry(&self, handle: ModelHandle) -> &Geometry {
+        &self.models[handle.0 as usize].0
+    }
+    pub fn collision(&self, handle: ModelHandle) -> &Collision {
+        &self.models[handle.0 as usize].1
+    }
+    // All handles, in registration order.
+    pub fn handles(&self) -> impl Iterator<Item = ModelHandle> {
+        (0..self.models.len() as u32).map(ModelHandle)
+    }
+}
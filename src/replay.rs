@@ -0,0 +1,305 @@
+/* Replay: records the input of every game tick, along with where the player ended up, and plays
+ * it back through GameState::update to check that the simulation still does the same thing. A
+ * recording is a text file:
+ *
+ *   tuning tick_rate=<ticks per second> player_force=<force> gravity=<acceleration>
+ *          max_slope=<degrees> step_height=<height>
+ *   scene <line>     one per line of the scene the recording started in, in order
+ *   tick move=<forward>,<backward>,<left>,<right> jump=<0|1> look=<x>,<y> position=<x>,<y>,<z>
+ *
+ * with one tick line per game tick. Recordings without a tuning line, or settings missing from
+ * it, use the defaults. Ticks are played back through a Simulation, one tick of the recorded
+ * rate apart however long they took when recorded. Nothing in the simulation is random, so
+ * there's no seed to store. '#' starts a comment line.
+ */
+use crate::{
+    config::{Tuning, MAX_TICKS_PER_SECOND},
+    input::TickInput,
+    resources::ParseError,
+    simulation::Simulation,
+};
+
+use anyhow::{bail, Context, Result};
+use cgmath::{MetricSpace, Point3};
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+    str::FromStr,
+};
+
+// How far a replayed position may be from the recorded one. Positions only match exactly with the
+// same build on the same platform, since math library functions differ in the last bits.
+const POSITION_TOLERANCE: f32 = 1e-3;
+
+// Writes a recording as the game runs.
+pub struct Recorder<W: Write> {
+    out: W,
+}
+impl Recorder<BufWriter<File>> {
+    pub fn create(path: &Path, scene_text: &str, tuning: Tuning) -> Result<Self> {
+        let file = File::create(path)
+            .with_context(|| format!("Could not create input recording {}", path.display()))?;
+        Recorder::new(BufWriter::new(file), scene_text, tuning)
+    }
+}
+impl<W: Write> Recorder<W> {
+    pub fn new(mut out: W, scene_text: &str, tuning: Tuning) -> Result<Self> {
+        writeln!(out, "# Input recording. Play it back with --replay <file>.")?;
+        writeln!(
+            out,
+            "tuning tick_rate={} player_force={} gravity={} max_slope={} step_height={}",
+            tuning.ticks_per_second,
+            tuning.player_force,
+            tuning.gravity,
+            tuning.max_slope,
+            tuning.step_height,
+        )?;
+        for line in scene_text.lines() {
+            writeln!(out, "scene {line}")?;
+        }
+        Ok(Recorder { out })
+    }
+    // Appends a tick. Flushes as it goes, so the recording is complete whenever the game stops.
+    pub fn record(&mut self, input: &TickInput, position: Point3<f32>) -> Result<()> {
+        writeln!(
+            self.out,
+            "tick move={},{},{},{} jump={} look={},{} position={},{},{}",
+            input.forward,
+            input.backward,
+            input.strafe_left,
+            input.strafe_right,
+            input.jump as u8,
+            input.look.0,
+            input.look.1,
+            position.x,
+            position.y,
+            position.z,
+        )?;
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+struct RecordedTick {
+    input: TickInput,
+    position: Point3<f32>,
+}
+
+pub struct Replay {
+    tuning: Tuning,
+    scene_text: String,
+    ticks: Vec<RecordedTick>,
+}
+impl Replay {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Could not read input recording {}", path.display()))?;
+        Ok(Self::parse(&path.display().to_string(), &text)?)
+    }
+    // file_name is only used for error messages.
+    pub fn parse(file_name: &str, text: &str) -> Result<Self, ParseError> {
+        let mut tuning = None;
+        let mut scene_text = String::new();
+        let mut ticks = vec![];
+        for (index, line) in text.lines().enumerate() {
+            let error = |message: String| ParseError {
+                file_name: file_name.to_string(),
+                line: index + 1,
+                message,
+            };
+            if let Some(args) = line.strip_prefix("tuning ") {
+                if tuning.is_some() || !(scene_text.is_empty() && ticks.is_empty()) {
+                    return Err(error("There can only be one tuning line, at the top".to_string()));
+                }
+                tuning = Some(parse_tuning(args).map_err(error)?);
+            } else if let Some(scene_line) = line.strip_prefix("scene ") {
+                if !ticks.is_empty() {
+                    return Err(error("Scene lines must come before the first tick".to_string()));
+                }
+                scene_text.push_str(scene_line);
+                scene_text.push('\n');
+            } else if let Some(args) = line.strip_prefix("tick ") {
+                ticks.push(parse_tick(args).map_err(error)?);
+            } else if !(line.trim().is_empty() || line.starts_with('#')) {
+                return Err(error("Expected a tuning, scene or tick line".to_string()));
+            }
+        }
+        Ok(Replay { tuning: tuning.unwrap_or_default(), scene_text, ticks })
+    }
+    pub fn ticks(&self) -> usize {
+        self.ticks.len()
+    }
+    // Runs every tick through a fresh GameState and checks the player's position after each one.
+    pub fn verify(&self) -> Result<()> {
+        self.play(|simulation, recorded| {
+            let position = simulation.player_position();
+            if position.distance(recorded.position) > POSITION_TOLERANCE {
+                bail!(
+                    "Tick {}: the player is at {:?}, but the recording has {:?}",
+                    simulation.ticks(),
+                    position,
+                    recorded.position
+                );
+            }
+            Ok(())
+        })
+    }
+    // Replaces the recorded positions with where the player ends up now. For when a change to the
+    // simulation is meant to change the outcome.
+    #[cfg(test)]
+    pub fn rerecord(&mut self) -> Result<()> {
+        let mut positions = vec![];
+        self.play(|simulation, _| {
+            positions.push(simulation.player_position());
+            Ok(())
+        })?;
+        for (tick, position) in self.ticks.iter_mut().zip(positions) {
+            tick.position = position;
+        }
+        Ok(())
+    }
+    #[cfg(test)]
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let mut recorder = Recorder::create(path, &self.scene_text, self.tuning)?;
+        for tick in &self.ticks {
+            recorder.record(&tick.input, tick.position)?;
+        }
+        Ok(())
+    }
+    fn play(
+        &self,
+        mut after_tick: impl FnMut(&Simulation, &RecordedTick) -> Result<()>,
+    ) -> Result<()> {
+        let mut simulation = Simulation::from_scene_text(&self.scene_text, self.tuning)?;
+        for tick in &self.ticks {
+            simulation.step(&tick.input);
+            after_tick(&simulation, tick)?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_tuning(args: &str) -> Result<Tuning, String> {
+    let mut tuning = Tuning::default();
+    for token in args.split_whitespace() {
+        let Some((key, value)) = token.split_once('=') else {
+            return Err(format!("Expected key=value, found '{token}'"));
+        };
+        let [number] = numbers::<1>(key, value)?;
+        match key {
+            "tick_rate" if number > 0.0 && number <= MAX_TICKS_PER_SECOND => {
+                tuning.ticks_per_second = number
+            }
+            "tick_rate" => {
+                return Err(format!(
+                    "tick_rate must be positive and at most {MAX_TICKS_PER_SECOND}, found '{value}'"
+                ))
+            }
+            "player_force" => tuning.player_force = number,
+            "gravity" => tuning.gravity = number,
+            "max_slope" if (0.0..90.0).contains(&number) => tuning.max_slope = number,
+            "max_slope" => {
+                return Err(format!("max_slope must be under 90 degrees, found '{value}'"))
+            }
+            "step_height" if number >= 0.0 => tuning.step_height = number,
+            "step_height" => return Err(format!("step_height can't be negative, found '{value}'")),
+            _ => {
+                return Err(format!(
+                    "Unknown key '{key}', expected tick_rate, player_force, gravity, max_slope or \
+                     step_height"
+                ))
+            }
+        }
+    }
+    Ok(tuning)
+}
+
+fn parse_tick(args: &str) -> Result<RecordedTick, String> {
+    let (mut movement, mut jump, mut look, mut position) = (None, None, None, None);
+    for token in args.split_whitespace() {
+        let Some((key, value)) = token.split_once('=') else {
+            return Err(format!("Expected key=value, found '{token}'"));
+        };
+        match key {
+            "move" => movement = Some(numbers::<4>(key, value)?),
+            "jump" => jump = Some(numbers::<1>(key, value)?[0] != 0.0),
+            "look" => look = Some(numbers::<2>(key, value)?),
+            "position" => position = Some(numbers::<3>(key, value)?),
+            _ => return Err(format!("Unknown key '{key}', expected move, jump, look or position")),
+        }
+    }
+    let missing = |key: &str| format!("Missing {key}=");
+    let [forward, backward, strafe_left, strafe_right] = movement.ok_or_else(|| missing("move"))?;
+    let [look_x, look_y] = look.ok_or_else(|| missing("look"))?;
+    let [x, y, z] = position.ok_or_else(|| missing("position"))?;
+    Ok(RecordedTick {
+        input: TickInput {
+            forward,
+            backward,
+            strafe_left,
+            strafe_right,
+            jump: jump.ok_or_else(|| missing("jump"))?,
+            look: (look_x, look_y),
+        },
+        position: Point3::new(x, y, z),
+    })
+}
+
+fn numbers<const N: usize>(key: &str, value: &str) -> Result<[f32; N], String> {
+    let mut numbers = [0.0; N];
+    let mut components = value.split(',');
+    for number in &mut numbers {
+        *number = components
+            .next()
+            .and_then(|component| f32::from_str(component).ok())
+            .filter(|number| number.is_finite())
+            .ok_or_else(|| format!("{key} expects {N} comma-separated numbers, found '{value}'"))?;
+    }
+    if components.next().is_some() {
+        return Err(format!("{key} expects {N} comma-separated numbers, found '{value}'"));
+    }
+    Ok(numbers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::path::PathBuf;
+
+    // Plays back every recording in tests/replays/. Run with CUBE_UPDATE_REPLAYS=1 to rewrite their
+    // positions after an intentional change to movement or physics.
+    #[test]
+    fn recorded_replays_still_match() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("replays");
+        let mut paths: Vec<PathBuf> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|extension| extension == "replay"))
+            .collect();
+        paths.sort();
+        assert!(!paths.is_empty(), "No recordings in {}", dir.display());
+        let update = std::env::var_os("CUBE_UPDATE_REPLAYS").is_some();
+        let failures: Vec<String> = paths
+            .iter()
+            .filter_map(|path| {
+                let mut replay = Replay::load(path).unwrap();
+                if update {
+                    replay.rerecord().unwrap();
+                    replay.save(path).unwrap();
+                    return None;
+                }
+                replay.verify().err().map(|e| format!("{}: {e:#}", path.display()))
+            })
+            .collect();
+        assert!(failures.is_empty(), "{}", failures.join("\n"));
+    }
+    #[test]
+    fn recorded_tick_rates_take_some_time() {
+        assert_eq!(parse_tuning("tick_rate=60").unwrap().ticks_per_second, 60.0);
+        for rate in ["inf", "0", "2e9"] {
+            assert!(parse_tuning(&format!("tick_rate={rate}")).is_err(), "took tick_rate {rate}");
+        }
+    }
+}
@@ -1,14 +1,35 @@
 use crate::texture;
 
+use std::fmt;
+
+// An error in a text asset, such as a scene or bindings file, pointing at the offending line.
+#[derive(Debug)]
+pub struct ParseError {
+    pub file_name: String,
+    pub line: usize,
+    pub message: String,
+}
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.file_name, self.line, self.message)
+    }
+}
+impl std::error::Error for ParseError {}
+
+// Where the build script copied an asset to.
+pub fn asset_path(file_name: &str) -> std::path::PathBuf {
+    std::path::Path::new(env!("OUT_DIR")).join("assets").join(file_name)
+}
+
 pub async fn load_binary(file_name: &str) -> anyhow::Result<Vec<u8>> {
-    let path = std::path::Path::new(env!("OUT_DIR")).join("assets").join(file_name);
+    let path = asset_path(file_name);
     let data = std::fs::read(path)?;
 
     Ok(data)
 }
 
 pub async fn load_string(file_name: &str) -> anyhow::Result<String> {
-    let path = std::path::Path::new(env!("OUT_DIR")).join("assets").join(file_name);
+    let path = asset_path(file_name);
     let txt = std::fs::read_to_string(path)?;
 
     Ok(txt)
@@ -0,0 +1,343 @@
+/* RigidBody: an instance that physics moves. A body has a mass and an inertia tensor, falls
+ * under gravity, and is pushed and spun by impulses where it touches other instances, other
+ * bodies or the player. Instances that aren't bodies never move, as if they were infinitely
+ * heavy.
+ *
+ * Contacts are solved with sequential impulses: each point where two things touch takes turns at
+ * cancelling the velocity into the contact, with the impulses so far kept so that no point ever
+ * pulls. Restitution makes fast hits bounce, and friction resists sliding with up to the
+ * friction coefficient times the push at that point. Anything still overlapping is pushed apart
+ * a little each tick rather than all at once, which would make resting bodies jitter.
+ */
+use cgmath::{EuclideanSpace, InnerSpace, Matrix3, Point3, Vector3, Zero};
+
+use crate::{
+    collision::{self, Contact, Convex, Placed},
+    game_state::{Instance, InstanceId},
+    physics::{Collision, Physics},
+    rotor::Rotor,
+};
+
+pub const DEFAULT_RESTITUTION: f32 = 0.2;
+pub const DEFAULT_FRICTION: f32 = 0.6;
+// Bodies never go faster than this.
+pub const MAX_SPEED: f32 = 50.0;
+// Air resistance against spinning, as torque per radian per second. Light bodies tumbling through
+// the air slow down; heavy ones hardly notice.
+pub const ANGULAR_DRAG: f32 = 0.002;
+
+const SOLVER_ITERATIONS: usize = 10;
+// How far a contact point can move in a tick and still count as the same one.
+const WARM_START_DISTANCE: f32 = 0.02;
+// The fraction of an overlap pushed apart each second, per tick.
+const BAUMGARTE: f32 = 0.2;
+// Overlaps this shallow are left alone, so that resting contacts stay touching.
+const PENETRATION_SLOP: f32 = 0.005;
+// Hits slower than this don't bounce, or nothing would ever come to rest.
+const RESTITUTION_THRESHOLD: f32 = 1.0;
+// How far apart two faces can be and still count as resting on each other.
+const CONTACT_TOLERANCE: f32 = 0.02;
+// A contact between two faces is held at its corners; more points than this are thinned out.
+const MAX_CONTACT_POINTS: usize = 4;
+
+#[derive(Clone)]
+pub struct RigidBody {
+    pub id: InstanceId,
+    // The center of mass is the instance's position, which for most models is the middle.
+    pub physics: Physics,
+    // How much of the speed into a hit it bounces back with, from 0 to 1.
+    pub restitution: f32,
+    pub friction: f32,
+}
+impl RigidBody {
+    // A body for the instance `id`, which starts where the instance is. The inertia is that of a
+    // solid box filling the collision shape's bounds.
+    pub fn new(
+        id: InstanceId,
+        instance: &Instance,
+        collision: &Collision,
+        mass: f32,
+        restitution: f32,
+        friction: f32,
+    ) -> Self {
+        let mut physics = Physics::new();
+        physics.position = Point3::from_vec(instance.position);
+        physics.angular_position = instance.rotation.into();
+        physics.mass = mass;
+        let size = (collision.bounds.max - collision.bounds.min) * instance.scale;
+        let (x, y, z) = (size.x * size.x, size.y * size.y, size.z * size.z);
+        #[rustfmt::skip]
+        let inverse_inertia = Matrix3::new(
+            12.0 / (mass * (y + z)), 0.0, 0.0,
+            0.0, 12.0 / (mass * (x + z)), 0.0,
+            0.0, 0.0, 12.0 / (mass * (x + y)),
+        );
+        physics.inverse_inertia = inverse_inertia;
+        RigidBody { id, physics, restitution, friction }
+    }
+    // Moves the instance to where the body is.
+    pub fn place(&self, instance: &mut Instance) {
+        instance.position = self.physics.position.to_vec();
+        instance.rotation = Rotor::from_quat(self.physics.angular_position);
+    }
+    // Pushes the body away from something without a body of its own, like the player, that has
+    // run into it at `point` with `velocity`. `normal` points from the body toward the other, and
+    // the body takes the impulse that would stop the two moving into each other if the other had
+    // `mass`.
+    pub fn push(
+        &mut self,
+        point: Vector3<f32>,
+        normal: Vector3<f32>,
+        velocity: Vector3<f32>,
+        mass: f32,
+    ) {
+        let offset = point - self.physics.position.to_vec();
+        let approach = (velocity - self.physics.velocity_at(offset)).dot(normal);
+        if approach >= 0.0 {
+            return;
+        }
+        let k = 1.0 / mass + inverse_effective_mass(&self.physics, offset, normal);
+        self.physics.apply_impulse(normal * (approach / k), offset);
+    }
+}
+
+// Two things that touch: body `a`, and body `b` or an instance that doesn't move.
+pub struct Touch {
+    pub a: usize,
+    pub b: Option<usize>,
+    // Moves a out of b.
+    pub contact: Contact,
+    pub points: Vec<ContactPoint>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ContactPoint {
+    // In world space.
+    pub position: Vector3<f32>,
+    // How far into the other shape the point is, which is negative for a gap.
+    pub depth: f32,
+}
+
+// Where two overlapping shapes touch: the corners of each that are level with the deepest point
+// of that shape, give or take CONTACT_TOLERANCE, and over the other shape. Two faces resting on
+// each other touch at every corner even when one is tipped a little. Crossed edges have no such
+// corners, and touch halfway between their deepest points.
+pub fn contact_points(a: &Placed, b: &Placed, contact: &Contact) -> Vec<ContactPoint> {
+    let mut points: Vec<ContactPoint> = resting_corners(a, contact)
+        .filter(|point| is_over(b, point.position, -contact.normal))
+        .chain(
+            resting_corners(b, &Contact { normal: -contact.normal, ..*contact })
+                .filter(|point| is_over(a, point.position, contact.normal)),
+        )
+        .collect();
+    if points.is_empty() {
+        points.push(ContactPoint { position: midpoint(a, b, contact), depth: contact.depth });
+    }
+    reduce(points)
+}
+
+// The corners of `shape` within CONTACT_TOLERANCE of its deepest point along the contact, which
+// moves `shape` out.
+fn resting_corners<'a>(
+    shape: &'a Placed,
+    contact: &Contact,
+) -> impl Iterator<Item = ContactPoint> + 'a {
+    let Contact { normal, depth } = *contact;
+    let deepest = shape.support(-normal).dot(normal);
+    shape.vertices().filter_map(move |position| {
+        let height = position.dot(normal) - deepest;
+        (height < CONTACT_TOLERANCE).then_some(ContactPoint { position, depth: depth - height })
+    })
+}
+
+// Whether a corner resting against `shape` is over it. The corner is moved a little way into the
+// shape along `into`, and toward its middle, so that corners right over its edges count too, as
+// they do when two boxes the same size are stacked.
+fn is_over(shape: &Placed, point: Vector3<f32>, into: Vector3<f32>) -> bool {
+    let toward_middle = shape.bounding_sphere().0 - point;
+    let sideways = toward_middle - into * toward_middle.dot(into);
+    let nudge = match sideways.magnitude() {
+        length if length > CONTACT_TOLERANCE => sideways * (CONTACT_TOLERANCE / length),
+        _ => sideways,
+    };
+    collision::contains(shape, point + into * 2.0 * CONTACT_TOLERANCE + nudge)
+}
+
+// Halfway between the point of `a` furthest into `b` and the point of `b` furthest into `a`.
+fn midpoint(a: &impl Convex, b: &impl Convex, contact: &Contact) -> Vector3<f32> {
+    (a.support(-contact.normal) + b.support(contact.normal)) / 2.0
+}
+
+// Keeps the points that span the most area: the deepest, then each one after furthest from those
+// already kept.
+fn reduce(mut points: Vec<ContactPoint>) -> Vec<ContactPoint> {
+    if points.len() <= MAX_CONTACT_POINTS {
+        return points;
+    }
+    let deepest =
+        (0..points.len()).max_by(|&i, &j| points[i].depth.total_cmp(&points[j].depth)).unwrap();
+    let mut kept = vec![points.swap_remove(deepest)];
+    while kept.len() < MAX_CONTACT_POINTS {
+        let distance = |point: &ContactPoint| {
+            kept.iter()
+                .map(|k| (point.position - k.position).magnitude2())
+                .fold(f32::INFINITY, f32::min)
+        };
+        let furthest = (0..points.len())
+            .max_by(|&i, &j| distance(&points[i]).total_cmp(&distance(&points[j])))
+            .unwrap();
+        kept.push(points.swap_remove(furthest));
+    }
+    kept
+}
+
+// 1 / the mass that an impulse along `direction` at `offset` from the center of mass moves.
+fn inverse_effective_mass(physics: &Physics, offset: Vector3<f32>, direction: Vector3<f32>) -> f32 {
+    let turn = (physics.world_inverse_inertia() * offset.cross(direction)).cross(offset);
+    1.0 / physics.mass + direction.dot(turn)
+}
+
+// One point of a Touch, as the solver sees it.
+#[derive(Clone)]
+struct Constraint {
+    a: usize,
+    b: Option<usize>,
+    point: Vector3<f32>,
+    normal: Vector3<f32>,
+    // From each body's center of mass to the point.
+    offset_a: Vector3<f32>,
+    offset_b: Vector3<f32>,
+    friction: f32,
+    // The speed apart along the normal to aim for, from bouncing or from pushing apart. Points
+    // with a gap between them aim for a negative speed, closing no more than the gap in a tick.
+    target: f32,
+    // What has been applied so far.
+    normal_impulse: f32,
+    friction_impulse: Vector3<f32>,
+}
+
+// Remembers the impulses from the last tick. A contact that's still there starts out with the
+// impulse it ended with, which is nearly right for anything at rest, rather than working up to it
+// from nothing; stacks would slowly shuffle apart otherwise.
+#[derive(Clone, Default)]
+pub struct Solver {
+    last_tick: Vec<Constraint>,
+}
+impl Solver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    // Changes the bodies' velocities so that nothing in `touches` moves further into anything
+    // else. Runs after gravity and before the bodies move, which gives them one tick to act.
+    pub fn solve(&mut self, bodies: &mut [RigidBody], touches: &[Touch], delta_t: f32) {
+        let mut constraints = constraints(bodies, touches, delta_t);
+        for constraint in &mut constraints {
+            let same = self.last_tick.iter().find(|last| {
+                (last.a, last.b) == (constraint.a, constraint.b)
+                    && (last.point - constraint.point).magnitude() < WARM_START_DISTANCE
+            });
+            if let Some(last) = same {
+                let normal = constraint.normal;
+                constraint.normal_impulse = last.normal_impulse;
+                constraint.friction_impulse =
+                    last.friction_impulse - normal * last.friction_impulse.dot(normal);
+                let impulse = normal * constraint.normal_impulse + constraint.friction_impulse;
+                apply(bodies, constraint, impulse);
+            }
+        }
+        for _ in 0..SOLVER_ITERATIONS {
+            for constraint in &mut constraints {
+                solve_normal(bodies, constraint);
+                solve_friction(bodies, constraint);
+            }
+        }
+        self.last_tick = constraints;
+    }
+}
+
+fn constraints(bodies: &[RigidBody], touches: &[Touch], delta_t: f32) -> Vec<Constraint> {
+    let mut constraints: Vec<Constraint> = vec![];
+    for touch in touches {
+        let body_a = &bodies[touch.a];
+        let body_b = touch.b.map(|b| &bodies[b]);
+        let (restitution, friction) = match body_b {
+            Some(b) => {
+                (body_a.restitution.max(b.restitution), (body_a.friction * b.friction).sqrt())
+            }
+            None => (body_a.restitution, body_a.friction),
+        };
+        for &ContactPoint { position: point, depth } in &touch.points {
+            let mut constraint = Constraint {
+                a: touch.a,
+                b: touch.b,
+                point,
+                normal: touch.contact.normal,
+                offset_a: point - body_a.physics.position.to_vec(),
+                offset_b: body_b.map_or(Vector3::zero(), |b| point - b.physics.position.to_vec()),
+                friction,
+                target: 0.0,
+                normal_impulse: 0.0,
+                friction_impulse: Vector3::zero(),
+            };
+            let approach = relative_velocity(bodies, &constraint).dot(constraint.normal);
+            let bounce =
+                if approach < -RESTITUTION_THRESHOLD { -restitution * approach } else { 0.0 };
+            constraint.target = if depth < 0.0 {
+                depth / delta_t
+            } else {
+                bounce.max(BAUMGARTE / delta_t * (depth - PENETRATION_SLOP).max(0.0))
+            };
+            constraints.push(constraint);
+        }
+    }
+    constraints
+}
+
+// How fast a's point is moving away from b's.
+fn relative_velocity(bodies: &[RigidBody], c: &Constraint) -> Vector3<f32> {
+    let a = bodies[c.a].physics.velocity_at(c.offset_a);
+    let b = c.b.map_or(Vector3::zero(), |b| bodies[b].physics.velocity_at(c.offset_b));
+    a - b
+}
+
+fn inverse_mass(bodies: &[RigidBody], c: &Constraint, direction: Vector3<f32>) -> f32 {
+    let a = inverse_effective_mass(&bodies[c.a].physics, c.offset_a, direction);
+    let b = c.b.map_or(0.0, |b| inverse_effective_mass(&bodies[b].physics, c.offset_b, direction));
+    a + b
+}
+
+fn apply(bodies: &mut [RigidBody], c: &Constraint, impulse: Vector3<f32>) {
+    bodies[c.a].physics.apply_impulse(impulse, c.offset_a);
+    if let Some(b) = c.b {
+        bodies[b].physics.apply_impulse(-impulse, c.offset_b);
+    }
+}
+
+fn solve_normal(bodies: &mut [RigidBody], c: &mut Constraint) {
+    let speed = relative_velocity(bodies, c).dot(c.normal);
+    let impulse = (c.target - speed) / inverse_mass(bodies, c, c.normal);
+    // The total can only ever push.
+    let total = (c.normal_impulse + impulse).max(0.0);
+    let applied = total - c.normal_impulse;
+    c.normal_impulse = total;
+    apply(bodies, c, c.normal * applied);
+}
+
+fn solve_friction(bodies: &mut [RigidBody], c: &mut Constraint) {
+    let velocity = relative_velocity(bodies, c);
+    let sliding = velocity - c.normal * velocity.dot(c.normal);
+    if sliding.magnitude2() < f32::EPSILON {
+        return;
+    }
+    let direction = sliding.normalize();
+    let impulse = -direction * sliding.magnitude() / inverse_mass(bodies, c, direction);
+    // The total is held inside the friction cone.
+    let mut total = c.friction_impulse + impulse;
+    let limit = c.friction * c.normal_impulse;
+    if total.magnitude() > limit {
+        total = total.normalize_to(limit);
+    }
+    let applied = total - c.friction_impulse;
+    c.friction_impulse = total;
+    apply(bodies, c, applied);
+}
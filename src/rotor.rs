@@ -25,10 +25,36 @@ impl Rotor {
     pub fn identity() -> Rotor {
         Rotor { s: 1.0, xy: 0.0, xz: 0.0, yz: 0.0 }
     }
-    #[allow(unused)]
     pub fn inverse(&self) -> Rotor {
         Rotor { s: self.s, xy: -self.xy, xz: -self.xz, yz: -self.yz }
     }
+    // Spherical interpolation from self (t = 0) to other (t = 1), along the shorter arc.
+    pub fn slerp(&self, other: &Rotor, t: f32) -> Rotor {
+        let mut dot =
+            self.s * other.s + self.xy * other.xy + self.xz * other.xz + self.yz * other.yz;
+        // R and -R are the same rotation; flip one so we don't go the long way around.
+        let other = if dot < 0.0 {
+            dot = -dot;
+            Rotor { s: -other.s, xy: -other.xy, xz: -other.xz, yz: -other.yz }
+        } else {
+            *other
+        };
+        let (a, b) = if dot > 0.9995 {
+            // Nearly parallel: sin(theta) is too small to divide by, and lerp is just as good.
+            (1.0 - t, t)
+        } else {
+            let theta = dot.acos();
+            (((1.0 - t) * theta).sin() / theta.sin(), (t * theta).sin() / theta.sin())
+        };
+        let r = Rotor {
+            s: a * self.s + b * other.s,
+            xy: a * self.xy + b * other.xy,
+            xz: a * self.xz + b * other.xz,
+            yz: a * self.yz + b * other.yz,
+        };
+        let norm = (r.s * r.s + r.xy * r.xy + r.xz * r.xz + r.yz * r.yz).sqrt();
+        Rotor { s: r.s / norm, xy: r.xy / norm, xz: r.xz / norm, yz: r.yz / norm }
+    }
     pub fn rotate_vector(&self, vec: Vector3<f32>) -> Vector3<f32> {
         // Calculate S = Rv
         let s_x = self.s * vec.x + self.xy * vec.y + self.xz * vec.z;
@@ -44,8 +70,14 @@ impl Rotor {
         }
     }
 }
-impl Into<[f32; 4]> for Rotor {
-    fn into(self) -> [f32; 4] {
-        [self.s, self.xy, self.xz, self.yz]
+// The inverse of Rotor::from_quat.
+impl From<Rotor> for Quaternion<f32> {
+    fn from(r: Rotor) -> Quaternion<f32> {
+        Quaternion::new(r.s, -r.yz, r.xz, -r.xy)
+    }
+}
+impl From<Rotor> for [f32; 4] {
+    fn from(r: Rotor) -> [f32; 4] {
+        [r.s, r.xy, r.xz, r.yz]
     }
 }
\ No newline at end of file
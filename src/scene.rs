@@ -0,0 +1,292 @@
+/* Scene files: a line-oriented description of the models, instances, light, camera and player
+ * spawn that make up a scene. GameState::from_scene builds the game from one, and WebGPUState
+ * loads the models it lists.
+ *
+ * Each line is a directive followed by arguments; '#' starts a comment.
+ *
+ *   model <name> <asset> [collider=<hull|mesh|none>]
+ *       Declares a model. <asset> is an .obj file in assets/, or builtin:cube or
+ *       builtin:double_cube. Its instances collide with the convex hull of its vertices, unless
+ *       collider says to use its triangles or nothing. Collision shapes are built here, so .obj
+ *       files are read while parsing.
+ *   instance <model> position=<x,y,z> [scale=<s>] [axis=<x,y,z> angle=<degrees>] [shader=<name>]
+ *            [mass=<m> [restitution=<e>] [friction=<mu>]]
+ *       Places an instance of a previously declared model. The axis does not need to be
+ *       normalized. Shaders are named like the Shader variants, in snake_case. With a mass, the
+ *       instance is a rigid body that falls, tumbles and can be pushed around; without one it
+ *       never moves. Restitution (0 to 1) is how bouncy it is, and friction how much it grips.
+ *   light [position=<x,y,z>] [color=<r,g,b>]
+ *   camera [direction=<x,y,z>] [fov=<degrees>] [near=<z>] [far=<z>]
+ *   player [spawn=<x,y,z>]
+ */
+use crate::{
+    game_state::{Instance, Shader},
+    light::Light,
+    model,
+    physics::ColliderKind,
+    registry::{ModelAsset, ModelHandle, ModelRegistry},
+    resources::{self, ParseError},
+    rigid_body::{DEFAULT_FRICTION, DEFAULT_RESTITUTION},
+    rotor::Rotor,
+};
+
+use cgmath::{EuclideanSpace, InnerSpace, Point3, Vector3, Zero};
+use std::{collections::HashMap, str::FromStr};
+
+#[derive(Clone, Copy)]
+pub struct SceneCamera {
+    pub direction: Vector3<f32>,
+    pub fovy: f32,
+    pub znear: f32,
+    pub zfar: f32,
+}
+
+// An instance that is a rigid body.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SceneBody {
+    // Index into Scene::instances.
+    pub instance: usize,
+    pub mass: f32,
+    pub restitution: f32,
+    pub friction: f32,
+}
+
+#[derive(Clone)]
+pub struct Scene {
+    pub models: ModelRegistry,
+    // In the order they appear in the file.
+    pub instances: Vec<(ModelHandle, Instance)>,
+    pub bodies: Vec<SceneBody>,
+    pub light: Light,
+    pub camera: SceneCamera,
+    pub player_spawn: Point3<f32>,
+}
+
+impl Scene {
+    // Loads a scene file from the assets directory.
+    pub async fn load(file_name: &str) -> anyhow::Result<Self> {
+        let text = resources::load_string(file_name).await?;
+        Ok(Self::parse(file_name, &text)?)
+    }
+    // Parses scene text. file_name is only used for error messages.
+    pub fn parse(file_name: &str, text: &str) -> Result<Self, ParseError> {
+        let mut scene = Scene {
+            models: ModelRegistry::new(),
+            instances: vec![],
+            bodies: vec![],
+            light: Light {
+                position: Vector3::new(2.0, 2.0, 2.0),
+                color: Vector3::new(1.0, 1.0, 1.0),
+            },
+            camera: SceneCamera {
+                direction: Vector3::new(0.0, -1.0, -2.0),
+                fovy: 45.0,
+                znear: 0.1,
+                zfar: 100.0,
+            },
+            player_spawn: Point3::new(0.0, 0.0, 0.0),
+        };
+        let mut seen_light = false;
+        for (index, line) in text.lines().enumerate() {
+            let error = |message: String| ParseError {
+                file_name: file_name.to_string(),
+                line: index + 1,
+                message,
+            };
+            let line = line.split('#').next().unwrap().trim();
+            let mut tokens = line.split_whitespace();
+            let Some(directive) = tokens.next() else {
+                continue;
+            };
+            match directive {
+                "model" => {
+                    let (Some(name), Some(asset)) = (tokens.next(), tokens.next()) else {
+                        return Err(error("Expected 'model <name> <asset>'".to_string()));
+                    };
+                    let args = Args::parse(tokens, &["collider"]).map_err(error)?;
+                    let collider = match args.get("collider") {
+                        None => ColliderKind::Hull,
+                        Some(name) => ColliderKind::from_name(name)
+                            .ok_or_else(|| error(format!("Unknown collider '{name}'")))?,
+                    };
+                    let asset = match asset {
+                        "builtin:cube" => ModelAsset::Cube,
+                        "builtin:double_cube" => ModelAsset::DoubleCube,
+                        _ if asset.starts_with("builtin:") => {
+                            return Err(error(format!("Unknown builtin model '{asset}'")));
+                        }
+                        _ => ModelAsset::Obj(asset.to_string()),
+                    };
+                    let geometry =
+                        model::load_geometry(&asset).map_err(|e| error(format!("{e:#}")))?;
+                    let collision = geometry.collision(collider);
+                    if scene.models.register(name, geometry, collision).is_none() {
+                        return Err(error(format!("Model '{name}' is already declared")));
+                    }
+                }
+                "instance" => {
+                    let Some(model) = tokens.next() else {
+                        return Err(error("Expected 'instance <model> ...'".to_string()));
+                    };
+                    let Some(handle) = scene.models.handle(model) else {
+                        return Err(error(format!("Unknown model '{model}'")));
+                    };
+                    let args = Args::parse(
+                        tokens,
+                        &[
+                            "position",
+                            "scale",
+                            "axis",
+                            "angle",
+                            "shader",
+                            "mass",
+                            "restitution",
+                            "friction",
+                        ],
+                    )
+                    .map_err(error)?;
+                    let position = args.required_vector("position").map_err(error)?;
+                    let scale = args.scalar("scale").map_err(error)?.unwrap_or(1.0);
+                    let rotation = match (args.vector("axis"), args.scalar("angle")) {
+                        (Ok(None), Ok(None)) => Rotor::identity(),
+                        (Ok(Some(axis)), Ok(Some(angle))) => {
+                            if axis.is_zero() {
+                                return Err(error("axis must not be zero".to_string()));
+                            }
+                            Rotor::from_axis_angle(axis.normalize(), cgmath::Deg(angle))
+                        }
+                        (Err(e), _) | (_, Err(e)) => return Err(error(e)),
+                        _ => {
+                            return Err(error("axis and angle must be given together".to_string()))
+                        }
+                    };
+                    let shader = match args.get("shader") {
+                        None => Shader::Texture,
+                        Some(name) => Shader::from_name(name)
+                            .ok_or_else(|| error(format!("Unknown shader '{name}'")))?,
+                    };
+                    let restitution = args.scalar("restitution").map_err(error)?;
+                    let friction = args.scalar("friction").map_err(error)?;
+                    match args.scalar("mass").map_err(error)? {
+                        Some(mass) if mass <= 0.0 => {
+                            return Err(error(format!("mass must be positive, found {mass}")));
+                        }
+                        Some(mass) => {
+                            let restitution = restitution.unwrap_or(DEFAULT_RESTITUTION);
+                            if !(0.0..=1.0).contains(&restitution) {
+                                return Err(error("restitution must be from 0 to 1".to_string()));
+                            }
+                            let friction = friction.unwrap_or(DEFAULT_FRICTION);
+                            if friction < 0.0 {
+                                return Err(error("friction must not be negative".to_string()));
+                            }
+                            let instance = scene.instances.len();
+                            scene.bodies.push(SceneBody { instance, mass, restitution, friction });
+                        }
+                        None if restitution.is_some() || friction.is_some() => {
+                            return Err(error(
+                                "restitution and friction only apply with a mass".to_string(),
+                            ));
+                        }
+                        None => {}
+                    }
+                    scene.instances.push((handle, Instance { position, scale, rotation, shader }));
+                }
+                "light" => {
+                    if seen_light {
+                        return Err(error("Only one light is supported".to_string()));
+                    }
+                    seen_light = true;
+                    let args = Args::parse(tokens, &["position", "color"]).map_err(error)?;
+                    if let Some(position) = args.vector("position").map_err(error)? {
+                        scene.light.position = position;
+                    }
+                    if let Some(color) = args.vector("color").map_err(error)? {
+                        scene.light.color = color;
+                    }
+                }
+                "camera" => {
+                    let args =
+                        Args::parse(tokens, &["direction", "fov", "near", "far"]).map_err(error)?;
+                    if let Some(direction) = args.vector("direction").map_err(error)? {
+                        if direction.is_zero() {
+                            return Err(error("direction must not be zero".to_string()));
+                        }
+                        scene.camera.direction = direction;
+                    }
+                    if let Some(fovy) = args.scalar("fov").map_err(error)? {
+                        scene.camera.fovy = fovy;
+                    }
+                    if let Some(znear) = args.scalar("near").map_err(error)? {
+                        scene.camera.znear = znear;
+                    }
+                    if let Some(zfar) = args.scalar("far").map_err(error)? {
+                        scene.camera.zfar = zfar;
+                    }
+                    if scene.camera.znear <= 0.0 || scene.camera.zfar <= scene.camera.znear {
+                        return Err(error("Expected 0 < near < far".to_string()));
+                    }
+                }
+                "player" => {
+                    let args = Args::parse(tokens, &["spawn"]).map_err(error)?;
+                    if let Some(spawn) = args.vector("spawn").map_err(error)? {
+                        scene.player_spawn = Point3::from_vec(spawn);
+                    }
+                }
+                _ => return Err(error(format!("Unknown directive '{directive}'"))),
+            }
+        }
+        Ok(scene)
+    }
+}
+
+// The key=value arguments of a single line.
+struct Args<'a> {
+    values: HashMap<&'a str, &'a str>,
+}
+impl<'a> Args<'a> {
+    fn parse(tokens: impl Iterator<Item = &'a str>, allowed: &[&str]) -> Result<Self, String> {
+        let mut values = HashMap::new();
+        for token in tokens {
+            let Some((key, value)) = token.split_once('=') else {
+                return Err(format!("Expected key=value, found '{token}'"));
+            };
+            if !allowed.contains(&key) {
+                return Err(format!("Unknown key '{key}', expected one of {}", allowed.join(", ")));
+            }
+            if values.insert(key, value).is_some() {
+                return Err(format!("'{key}' is given more than once"));
+            }
+        }
+        Ok(Args { values })
+    }
+    fn get(&self, key: &str) -> Option<&'a str> {
+        self.values.get(key).copied()
+    }
+    fn scalar(&self, key: &str) -> Result<Option<f32>, String> {
+        self.get(key).map(|value| parse_number(key, value)).transpose()
+    }
+    fn vector(&self, key: &str) -> Result<Option<Vector3<f32>>, String> {
+        let Some(value) = self.get(key) else {
+            return Ok(None);
+        };
+        let components = value
+            .split(',')
+            .map(|component| parse_number(key, component))
+            .collect::<Result<Vec<_>, _>>()?;
+        match components[..] {
+            [x, y, z] => Ok(Some(Vector3::new(x, y, z))),
+            _ => Err(format!("{key} needs three comma-separated numbers, found '{value}'")),
+        }
+    }
+    fn required_vector(&self, key: &str) -> Result<Vector3<f32>, String> {
+        self.vector(key)?.ok_or_else(|| format!("Missing {key}=<x,y,z>"))
+    }
+}
+
+fn parse_number(key: &str, value: &str) -> Result<f32, String> {
+    match f32::from_str(value) {
+        Ok(number) if number.is_finite() => Ok(number),
+        _ => Err(format!("{key} expects a number, found '{value}'")),
+    }
+}
@@ -0,0 +1,299 @@
+/* Simulation: steps a GameState tick by tick with scripted input, without a window, GPU or
+ * clock. Each step is exactly one tick of game time however long it takes to compute, so the
+ * same scene and input always end in the same place. Used by replays and tests. */
+use crate::{
+    config::Tuning, game_state::GameState, input::TickInput, resources::ParseError, scene::Scene,
+};
+
+use cgmath::Point3;
+
+pub struct Simulation {
+    game_state: GameState,
+}
+
+impl Simulation {
+    pub fn new(scene: &Scene, tuning: Tuning) -> Self {
+        // The aspect ratio only affects rendering.
+        Simulation { game_state: GameState::from_scene(scene, 1.0, tuning) }
+    }
+    pub fn from_scene_text(text: &str, tuning: Tuning) -> Result<Self, ParseError> {
+        Ok(Self::new(&Scene::parse("simulation", text)?, tuning))
+    }
+    pub fn step(&mut self, input: &TickInput) {
+        self.game_state.update(input);
+    }
+    // How many ticks have been simulated.
+    pub fn ticks(&self) -> usize {
+        self.game_state.tick() as usize
+    }
+    pub fn player_position(&self) -> Point3<f32> {
+        self.game_state.player_position()
+    }
+}
+
+// Scripting and inspection for tests, which replays don't need.
+#[cfg(test)]
+impl Simulation {
+    // Steps `ticks` times with the same input. Edge-triggered input like jump is only applied on
+    // the first of them, as if the player had pressed it once and held on.
+    pub fn hold(&mut self, ticks: usize, input: &TickInput) {
+        let mut input = *input;
+        for _ in 0..ticks {
+            self.step(&input);
+            input.jump = false;
+        }
+    }
+    // Steps `ticks` times, asking `script` for the input of each tick. It gets the number of the
+    // tick it's for, counting from 0 at the start of the simulation.
+    pub fn run(&mut self, ticks: usize, mut script: impl FnMut(usize) -> TickInput) {
+        for _ in 0..ticks {
+            let input = script(self.ticks());
+            self.step(&input);
+        }
+    }
+    pub fn player_velocity(&self) -> cgmath::Vector3<f32> {
+        self.game_state.player_velocity()
+    }
+    pub fn camera(&self) -> crate::camera::Camera {
+        self.game_state.get_camera()
+    }
+    pub fn game_state(&self) -> &GameState {
+        &self.game_state
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        constants::{DEFAULT_GRAVITY, DEFAULT_PLAYER_FORCE},
+        game_state::Instance,
+    };
+
+    use cgmath::{InnerSpace, MetricSpace, Vector3};
+
+    // A floor 40 wide whose top is at y = -5.5, so the player's center rests at y = -5.
+    const FLOOR: &str = "model floor builtin:cube
+                         instance floor position=0,-25.5,0 scale=20";
+
+    // The player on the floor; the cube is only there to look at.
+    fn simulation() -> Simulation {
+        let scene = format!(
+            "{FLOOR}
+             model cube builtin:cube
+             instance cube position=0,0,-10
+             player spawn=0,-5,0
+             camera direction=0,0,-1"
+        );
+        Simulation::from_scene_text(&scene, Tuning::default()).unwrap()
+    }
+
+    #[test]
+    fn standing_still_stays_put() {
+        let mut sim = simulation();
+        let start = sim.player_position();
+        sim.hold(100, &TickInput::default());
+        assert_eq!(sim.ticks(), 100);
+        assert!(sim.player_position().distance(start) < 1e-5);
+        assert!(sim.player_velocity().magnitude() < 1e-5);
+    }
+
+    #[test]
+    fn holding_forward_accelerates_along_the_camera() {
+        let mut sim = simulation();
+        let start = sim.player_position();
+        sim.hold(100, &TickInput { forward: 1.0, ..TickInput::default() });
+        // A second at the default player force from rest, with unit mass.
+        let expected = 0.5 * DEFAULT_PLAYER_FORCE;
+        let moved = sim.player_position() - start;
+        assert!((moved.z + expected).abs() < 0.05, "moved {moved:?}, expected {expected} along -z");
+        assert!(moved.x.abs() < 1e-4 && moved.y.abs() < 1e-4, "drifted sideways: {moved:?}");
+        assert!((sim.player_velocity().z + DEFAULT_PLAYER_FORCE).abs() < 0.05);
+    }
+
+    #[test]
+    fn letting_go_comes_to_a_stop() {
+        let mut sim = simulation();
+        sim.hold(100, &TickInput { strafe_right: 1.0, ..TickInput::default() });
+        assert!(sim.player_velocity().x > 1.0);
+        sim.hold(200, &TickInput::default());
+        assert!(sim.player_velocity().magnitude() < 0.01, "still at {:?}", sim.player_velocity());
+        let stopped = sim.player_position();
+        sim.hold(100, &TickInput::default());
+        assert!((sim.player_position() - stopped).magnitude() < 1e-4, "crept from {stopped:?}");
+    }
+
+    #[test]
+    fn jump_lands_again() {
+        let mut sim = simulation();
+        let floor = sim.player_position().y;
+        let mut peak = floor;
+        sim.step(&TickInput { jump: true, ..TickInput::default() });
+        while sim.ticks() < 200 {
+            sim.step(&TickInput::default());
+            peak = peak.max(sim.player_position().y);
+        }
+        // v^2 / 2g for the 5 units per second a jump adds.
+        let expected = 25.0 / (2.0 * -DEFAULT_GRAVITY);
+        assert!((peak - floor - expected).abs() < 0.1, "peaked {} up", peak - floor);
+        // Sweeps stop the player just short of the floor, rather than exactly on it.
+        assert!((sim.player_position().y - floor).abs() < 1e-4);
+        let ground = sim.game_state().player_ground().expect("not on the floor");
+        assert!((ground - Vector3::unit_y()).magnitude() < 1e-6, "on {ground:?}");
+    }
+
+    #[test]
+    fn walls_stop_the_player_and_let_it_slide() {
+        // A wall ahead, with its near face at z = -4, that the player walks into at 45 degrees.
+        let scene = format!(
+            "{FLOOR}
+             model cube builtin:cube
+             instance cube position=0,-5,-24 scale=20
+             player spawn=0,-5,0
+             camera direction=-1,0,-1"
+        );
+        let mut sim = Simulation::from_scene_text(&scene, Tuning::default()).unwrap();
+        sim.hold(200, &TickInput { forward: 1.0, ..TickInput::default() });
+        let position = sim.player_position();
+        // The player's box is a quarter wide, so it stops an eighth short of the face...
+        assert!((position.z + 4.0 - 0.125).abs() < 1e-3, "stopped at {position:?}");
+        // ...and keeps going left along it.
+        assert!(position.x < -5.0, "stuck at {position:?}");
+        assert!(sim.player_velocity().z.abs() < 1e-3);
+    }
+
+    #[test]
+    fn low_steps_are_climbed_and_tall_ones_are_not() {
+        // A block ahead whose top is `height` above the floor.
+        let walk_into = |height: f32| {
+            let scene = format!(
+                "{FLOOR}
+                 model cube builtin:cube
+                 instance cube position=0,{},-3 scale=1
+                 player spawn=0,-5,0
+                 camera direction=0,0,-1",
+                -6.5 + height
+            );
+            let mut sim = Simulation::from_scene_text(&scene, Tuning::default()).unwrap();
+            sim.hold(100, &TickInput { forward: 1.0, ..TickInput::default() });
+            sim
+        };
+        let sim = walk_into(0.2);
+        let position = sim.player_position();
+        assert!((position.y + 4.8).abs() < 1e-3 && position.z < -2.5, "at {position:?}");
+        let ground = sim.game_state().player_ground().expect("not on the floor");
+        assert!((ground - Vector3::unit_y()).magnitude() < 1e-6, "on {ground:?}");
+
+        let position = walk_into(0.5).player_position();
+        assert!((position.y + 5.0).abs() < 1e-3, "climbed to {position:?}");
+        assert!((position.z + 2.0 - 0.125).abs() < 1e-3, "stopped at {position:?}");
+    }
+
+    #[test]
+    fn gentle_slopes_are_walked_up_and_steep_ones_are_not() {
+        // A ramp rising away from the player, starting from the floor at z = -2.
+        let walk_up = |degrees: f32| {
+            let (sin, cos) = degrees.to_radians().sin_cos();
+            let center = Vector3::new(0.0, -5.5, -2.0) - 5.0 * Vector3::new(0.0, cos, sin);
+            let scene = format!(
+                "{FLOOR}
+                 model cube builtin:cube
+                 instance cube position={},{},{} scale=5 axis=1,0,0 angle={degrees}
+                 player spawn=0,-5,0
+                 camera direction=0,0,-1",
+                center.x, center.y, center.z
+            );
+            let mut sim = Simulation::from_scene_text(&scene, Tuning::default()).unwrap();
+            sim.hold(100, &TickInput { forward: 1.0, ..TickInput::default() });
+            sim
+        };
+        let sim = walk_up(30.0);
+        let position = sim.player_position();
+        assert!(position.y > -4.8 && position.z < -2.5, "at {position:?}");
+        let normal = sim.game_state().player_ground().expect("not on the ramp");
+        assert!((normal.y - 30f32.to_radians().cos()).abs() < 1e-3, "standing on {normal:?}");
+        // Letting go coasts to a stop, rather than sliding back down.
+        let mut sim = sim;
+        sim.hold(200, &TickInput::default());
+        assert!(sim.player_position().y > position.y, "slid back to {:?}", sim.player_position());
+        assert!(sim.player_velocity().magnitude() < 0.01, "still at {:?}", sim.player_velocity());
+        let stopped = sim.player_position();
+        sim.hold(100, &TickInput::default());
+        assert!((sim.player_position() - stopped).magnitude() < 1e-4, "crept from {stopped:?}");
+
+        let position = walk_up(60.0).player_position();
+        assert!(position.y < -4.9 && position.z > -2.5, "climbed to {position:?}");
+    }
+
+    #[test]
+    fn looking_turns_the_camera() {
+        let mut sim = simulation();
+        // Half a degree a tick for 90 ticks, then another 45 at once.
+        sim.run(91, |tick| {
+            let degrees = if tick < 90 { 0.5 } else { 45.0 };
+            TickInput { look: (degrees, 0.0), ..TickInput::default() }
+        });
+        // 90 degrees to the right of -z is +x.
+        let direction = sim.camera().direction.normalize();
+        assert!((direction - Vector3::unit_x()).magnitude() < 1e-3, "facing {direction:?}");
+    }
+
+    // The player on the floor with some boxes.
+    fn scene_with_boxes(boxes: &str) -> Simulation {
+        let scene = format!(
+            "{FLOOR}
+             model box builtin:cube
+             {boxes}
+             player spawn=0,-5,0
+             camera direction=0,0,-1"
+        );
+        Simulation::from_scene_text(&scene, Tuning::default()).unwrap()
+    }
+
+    fn boxes(sim: &Simulation) -> &[Instance] {
+        let game_state = sim.game_state();
+        game_state.instances(game_state.models().handle("box").unwrap())
+    }
+
+    fn is_upright(instance: &Instance) -> bool {
+        let up = instance.rotation.rotate_vector(Vector3::unit_y());
+        [Vector3::unit_x(), Vector3::unit_y(), Vector3::unit_z()]
+            .iter()
+            .any(|axis| up.dot(*axis).abs() > 0.999)
+    }
+
+    #[test]
+    fn stacks_stand_and_tipped_boxes_fall_flat() {
+        let mut sim = scene_with_boxes(
+            "instance box position=3,-5,0 scale=0.5 mass=1
+             instance box position=3,-4,0 scale=0.5 mass=1
+             instance box position=3,-3,0 scale=0.5 mass=1
+             instance box position=-3,-3,0 scale=0.5 mass=1 axis=0,0,1 angle=30",
+        );
+        sim.hold(500, &TickInput::default());
+        let boxes = boxes(&sim);
+        for (instance, height) in boxes.iter().zip([-5.0, -4.0, -3.0]) {
+            let expected = Vector3::new(3.0, height, 0.0);
+            assert!((instance.position - expected).magnitude() < 0.01, "{:?}", instance.position);
+            assert!(is_upright(instance));
+        }
+        // Falling on its corner knocks it over onto a face.
+        assert!((boxes[3].position.y + 5.0).abs() < 0.01, "{:?}", boxes[3].position);
+        assert!(is_upright(&boxes[3]));
+    }
+
+    #[test]
+    fn running_into_a_stack_knocks_it_over() {
+        let mut sim = scene_with_boxes(
+            "instance box position=0,-5.25,-3 scale=0.25 mass=0.2
+             instance box position=0,-4.75,-3 scale=0.25 mass=0.2",
+        );
+        sim.hold(150, &TickInput { forward: 1.0, ..TickInput::default() });
+        sim.hold(150, &TickInput::default());
+        let boxes = boxes(&sim);
+        // The bottom box slides away, and the top one drops to the floor behind it.
+        assert!(boxes[0].position.z < -3.5, "{:?}", boxes[0].position);
+        assert!((boxes[1].position.y + 5.25).abs() < 0.01, "{:?}", boxes[1].position);
+        assert!(is_upright(&boxes[1]));
+    }
+}
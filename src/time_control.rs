@@ -0,0 +1,163 @@
+/* TimeControl: decides when the game thread runs a tick. The game normally ticks in real time,
+ * but it can be paused, stepped one tick at a time while paused, and slowed down or sped up.
+ * Shaders follow along, since their time comes from the game's snapshots.
+ *
+ *   F5  pause or resume
+ *   F6  step one tick while paused
+ *   F7  half speed
+ *   F8  double speed
+ */
+use crate::events::{Event, Key};
+
+use std::time::{Duration, Instant};
+
+pub const PAUSE_KEY: Key = Key::F(5);
+pub const STEP_KEY: Key = Key::F(6);
+pub const SLOWER_KEY: Key = Key::F(7);
+pub const FASTER_KEY: Key = Key::F(8);
+
+pub const MIN_TIME_SCALE: f32 = 1.0 / 16.0;
+pub const MAX_TIME_SCALE: f32 = 8.0;
+// How often the game thread checks for input while paused.
+pub const PAUSED_POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+pub struct TimeControl {
+    time_per_tick: Duration,
+    time_scale: f32,
+    paused: bool,
+    // Ticks asked for with step() that haven't run yet.
+    pending_steps: u32,
+    // When the last tick was due, in real time.
+    last_tick: Instant,
+}
+impl TimeControl {
+    pub fn new(time_per_tick: Duration, now: Instant) -> Self {
+        TimeControl {
+            time_per_tick,
+            time_scale: 1.0,
+            paused: false,
+            pending_steps: 0,
+            last_tick: now,
+        }
+    }
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+    // Ticks pick up from `now`, rather than catching up on the time spent paused.
+    pub fn resume(&mut self, now: Instant) {
+        if self.paused {
+            self.paused = false;
+            self.pending_steps = 0;
+            self.last_tick = now;
+        }
+    }
+    pub fn toggle_pause(&mut self, now: Instant) {
+        if self.paused {
+            self.resume(now);
+        } else {
+            self.pause();
+        }
+    }
+    // Runs one more tick while paused. Does nothing while running.
+    pub fn step(&mut self) {
+        if self.paused {
+            self.pending_steps += 1;
+        }
+    }
+    pub fn time_scale(&self) -> f32 {
+        self.time_scale
+    }
+    // 1 is real time, 0.5 half speed. The scale is clamped to MIN_TIME_SCALE..=MAX_TIME_SCALE.
+    pub fn set_time_scale(&mut self, time_scale: f32) {
+        self.time_scale = time_scale.clamp(MIN_TIME_SCALE, MAX_TIME_SCALE);
+    }
+    // How much real time a tick takes at the current scale.
+    pub fn real_time_per_tick(&self) -> Duration {
+        self.time_per_tick.div_f32(self.time_scale)
+    }
+    // When the last tick was due. Stepped ticks are due when they run.
+    pub fn last_tick(&self) -> Instant {
+        self.last_tick
+    }
+    // Whether a tick is due at `now`. Each call that returns true uses up that tick, so callers
+    // loop until it returns false.
+    pub fn next_tick(&mut self, now: Instant) -> bool {
+        if self.paused {
+            if self.pending_steps == 0 {
+                return false;
+            }
+            self.pending_steps -= 1;
+            self.last_tick = now;
+            return true;
+        }
+        let real_time_per_tick = self.real_time_per_tick();
+        if now.saturating_duration_since(self.last_tick) < real_time_per_tick {
+            return false;
+        }
+        self.last_tick += real_time_per_tick;
+        true
+    }
+    // How long until the next tick is due, or None while paused with no steps waiting.
+    pub fn time_to_next_tick(&self, now: Instant) -> Option<Duration> {
+        match (self.paused, self.pending_steps) {
+            (true, 0) => None,
+            (true, _) => Some(Duration::ZERO),
+            (false, _) => {
+                Some((self.last_tick + self.real_time_per_tick()).saturating_duration_since(now))
+            }
+        }
+    }
+    // Acts on the hotkeys at the top of this file. Returns whether `event` was one of them.
+    pub fn handle(&mut self, event: &Event, now: Instant) -> bool {
+        let Event::KeyPressed { key, repeat } = *event else {
+            return false;
+        };
+        match key {
+            PAUSE_KEY if !repeat => self.toggle_pause(now),
+            // Holding the step key keeps stepping.
+            STEP_KEY => self.step(),
+            SLOWER_KEY if !repeat => self.set_time_scale(self.time_scale / 2.0),
+            FASTER_KEY if !repeat => self.set_time_scale(self.time_scale * 2.0),
+            PAUSE_KEY | SLOWER_KEY | FASTER_KEY => {}
+            _ => return false,
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TICK: Duration = Duration::from_millis(10);
+
+    fn ticks(control: &mut TimeControl, now: Instant) -> usize {
+        std::iter::from_fn(|| control.next_tick(now).then_some(())).count()
+    }
+
+    #[test]
+    fn pause_step_and_scale() {
+        let start = Instant::now();
+        let mut control = TimeControl::new(TICK, start);
+        assert_eq!(ticks(&mut control, start + TICK * 3), 3);
+
+        control.pause();
+        control.step();
+        control.step();
+        assert_eq!(ticks(&mut control, start + TICK * 50), 2);
+        assert_eq!(control.time_to_next_tick(start + TICK * 50), None);
+
+        // Resuming doesn't make up for the time spent paused.
+        control.resume(start + TICK * 100);
+        assert_eq!(ticks(&mut control, start + TICK * 101), 1);
+
+        control.set_time_scale(0.5);
+        assert_eq!(ticks(&mut control, start + TICK * 102), 0);
+        assert_eq!(ticks(&mut control, start + TICK * 103), 1);
+        control.set_time_scale(1000.0);
+        assert_eq!(control.time_scale(), MAX_TIME_SCALE);
+    }
+}
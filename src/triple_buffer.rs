@@ -0,0 +1,109 @@
+/* TripleBuffer: hands the newest value from one writer thread to one reader thread without locks
+ * or allocation. There are three slots: the writer owns one, the reader owns one, and the third
+ * is the most recently published value (or the reader's previous one, if nothing new has been
+ * published since). Publishing and taking are a single atomic swap of slot indices, so neither
+ * side ever waits, and values the reader never got around to are simply overwritten. */
+use std::{
+    cell::UnsafeCell,
+    sync::{
+        atomic::{AtomicU8, Ordering},
+        Arc,
+    },
+};
+
+const INDEX_MASK: u8 = 0b11;
+// Set in `middle` when its slot was published and not yet taken by the reader.
+const FRESH: u8 = 0b100;
+
+struct Shared<T> {
+    slots: [UnsafeCell<T>; 3],
+    middle: AtomicU8,
+}
+// Each slot is only ever accessed by whichever side currently owns its index.
+unsafe impl<T: Send> Sync for Shared<T> {}
+
+pub struct Writer<T> {
+    shared: Arc<Shared<T>>,
+    back: u8,
+}
+pub struct Reader<T> {
+    shared: Arc<Shared<T>>,
+    front: u8,
+}
+
+// All three slots start as copies of initial, which is what the reader sees until the first
+// publish.
+pub fn triple_buffer<T: Clone>(initial: T) -> (Writer<T>, Reader<T>) {
+    let shared = Arc::new(Shared {
+        slots: [
+            UnsafeCell::new(initial.clone()),
+            UnsafeCell::new(initial.clone()),
+            UnsafeCell::new(initial),
+        ],
+        middle: AtomicU8::new(1),
+    });
+    (Writer { shared: Arc::clone(&shared), back: 0 }, Reader { shared, front: 2 })
+}
+
+impl<T> Writer<T> {
+    // The slot to fill in before publishing. It holds whatever was written to it last time it was
+    // the back slot, which lets callers reuse its allocations.
+    pub fn back_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.shared.slots[self.back as usize].get() }
+    }
+    // Makes the back slot the newest value, and takes another slot to write into next.
+    pub fn publish(&mut self) {
+        let previous = self.shared.middle.swap(self.back | FRESH, Ordering::AcqRel);
+        self.back = previous & INDEX_MASK;
+    }
+}
+
+impl<T> Reader<T> {
+    // Whether a value the reader hasn't seen has been published. Only update() clears this, so
+    // it stays true until the reader acts on it.
+    pub fn is_fresh(&self) -> bool {
+        self.shared.middle.load(Ordering::Relaxed) & FRESH != 0
+    }
+    // Switches to the newest published value, if there is one the reader hasn't seen. Returns
+    // whether it did.
+    pub fn update(&mut self) -> bool {
+        if !self.is_fresh() {
+            return false;
+        }
+        let previous = self.shared.middle.swap(self.front, Ordering::AcqRel);
+        self.front = previous & INDEX_MASK;
+        true
+    }
+    pub fn read(&self) -> &T {
+        unsafe { &*self.shared.slots[self.front as usize].get() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The writer fills every element of its slot with a counter before publishing. A torn read
+    // would show mixed counters, and a stale slot coming back would make the counter go backwards.
+    #[test]
+    fn reader_sees_complete_values_in_order() {
+        const WRITES: u64 = 100_000;
+        let (mut writer, mut reader) = triple_buffer([0u64; 16]);
+        let writer_thread = std::thread::spawn(move || {
+            for i in 1..=WRITES {
+                writer.back_mut().fill(i);
+                writer.publish();
+            }
+        });
+        let mut last = 0;
+        while last < WRITES {
+            reader.update();
+            let value = reader.read();
+            assert!(value.iter().all(|v| *v == value[0]), "torn read: {value:?}");
+            assert!(value[0] >= last, "went from {last} back to {}", value[0]);
+            last = value[0];
+        }
+        writer_thread.join().unwrap();
+        assert!(!reader.update());
+    }
+}